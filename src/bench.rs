@@ -0,0 +1,112 @@
+// Headless benchmark, run with `survivor --bench`. It compares the spatial
+// grid against checking every pair of enemies and measures whole game steps
+// for growing numbers of enemies.
+
+use crate::enemies;
+use crate::game;
+use crate::grid;
+use crate::random;
+use crate::weapons;
+
+use std::time;
+
+const COUNTS: [usize; 5] = [250, 500, 1000, 2000, 4000];
+const STEPS: usize = 50;
+// average distance between enemies, so that density stays the same
+const SPACING: f32 = 12.0;
+
+fn place_enemies(game: &mut game::Game, t: &'static enemies::EnemyType, n: usize) -> f32 {
+    let mut rng = random::Rng::new();
+    let side = (n as f32).sqrt() * SPACING;
+    for _ in 0..n {
+        let p = game::Pos {
+            x: (rng.gen_f32() - 0.5) * side,
+            y: (rng.gen_f32() - 0.5) * side,
+        };
+        let enemy = enemies::Enemy::new(game.i_enemy, t, p, None, game.scaling());
+        game.enemies.push(enemy);
+        game.i_enemy += 1;
+    }
+    return side;
+}
+
+fn overlaps(a: &enemies::Enemy, b: &enemies::Enemy) -> bool {
+    return a.id != b.id && a.p.in_radius(&b.p, a.t.size + b.t.size);
+}
+
+fn count_pairs(enemies: &[enemies::Enemy]) -> usize {
+    let mut count = 0;
+    for a in enemies.iter() {
+        for b in enemies.iter() {
+            if overlaps(a, b) {
+                count += 1;
+            }
+        }
+    }
+    return count;
+}
+
+fn count_pairs_grid(enemies: &[enemies::Enemy], grid: &mut grid::Grid<usize>) -> usize {
+    let size = enemies.iter().map(|e| e.t.size).fold(0.0, f32::max);
+    grid.clear();
+    for (i, enemy) in enemies.iter().enumerate() {
+        grid.insert(enemy.p, i);
+    }
+
+    let mut count = 0;
+    for a in enemies.iter() {
+        for &(_, j) in grid.nearby(&a.p, a.t.size + size) {
+            if overlaps(a, &enemies[j]) {
+                count += 1;
+            }
+        }
+    }
+    return count;
+}
+
+fn millis(duration: time::Duration) -> f32 {
+    return duration.as_secs_f32() * 1000.0;
+}
+
+pub fn run(weapons: Vec<weapons::Weapon>, timeline: enemies::Timeline) {
+    let t = timeline.waves[0]
+        .spawns
+        .first()
+        .map(|(t, _)| *t)
+        .or(timeline.reaper)
+        .expect("no enemy type to benchmark with");
+
+    println!("enemies   all pairs        grid   speedup   game step");
+    for n in COUNTS {
+        let mut game = game::Game::new(weapons.clone(), timeline.clone());
+        let side = place_enemies(&mut game, t, n);
+
+        let start = time::Instant::now();
+        let expected = count_pairs(&game.enemies);
+        let brute_force = start.elapsed();
+
+        let mut grid = grid::Grid::new(32.0);
+        let start = time::Instant::now();
+        let found = count_pairs_grid(&game.enemies, &mut grid);
+        let with_grid = start.elapsed();
+        assert_eq!(expected, found, "grid missed overlapping enemies");
+
+        let start = time::Instant::now();
+        for _ in 0..STEPS {
+            if game.paused() {
+                game.choose(0);
+            }
+            game.step(0.04, side * 2.0, side * 2.0);
+        }
+        let step = start.elapsed() / STEPS as u32;
+
+        println!(
+            "{:7} {:9.2}ms {:9.2}ms {:8.1}x {:9.2}ms",
+            n,
+            millis(brute_force),
+            millis(with_grid),
+            brute_force.as_secs_f32() / with_grid.as_secs_f32(),
+            millis(step),
+        );
+    }
+}
@@ -0,0 +1,168 @@
+// Parser for the plain text definition files in `data/`.
+//
+// The files consist of sections that start with a `[name]` line and contain
+// `key = value` lines. Empty lines and lines starting with `#` are ignored.
+
+pub struct Field {
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+}
+
+pub struct Section {
+    pub name: String,
+    pub line: usize,
+    pub fields: Vec<Field>,
+}
+
+impl Field {
+    pub fn error(&self, msg: &str) -> String {
+        return format!("line {}: {}", self.line, msg);
+    }
+
+    pub fn f32(&self) -> Result<f32, String> {
+        return self.value.parse().map_err(|_| {
+            self.error(&format!(
+                "`{}` must be a number, got `{}`",
+                self.key, self.value
+            ))
+        });
+    }
+
+    pub fn u8(&self) -> Result<u8, String> {
+        return self.value.parse().map_err(|_| {
+            self.error(&format!(
+                "`{}` must be a whole number between 0 and 255, got `{}`",
+                self.key, self.value
+            ))
+        });
+    }
+
+    pub fn bool(&self) -> Result<bool, String> {
+        return match self.value.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(self.error(&format!(
+                "`{}` must be `true` or `false`, got `{}`",
+                self.key, self.value
+            ))),
+        };
+    }
+
+    pub fn time(&self) -> Result<f32, String> {
+        return parse_time(&self.value).ok_or_else(|| {
+            self.error(&format!(
+                "`{}` must be a time like `1:30`, got `{}`",
+                self.key, self.value
+            ))
+        });
+    }
+
+    pub fn list(&self) -> Vec<&str> {
+        return self
+            .value
+            .split(',')
+            .map(|item| item.trim())
+            .filter(|item| !item.is_empty())
+            .collect();
+    }
+}
+
+impl Section {
+    pub fn error(&self, msg: &str) -> String {
+        return format!("line {}: [{}] {}", self.line, self.name, msg);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Field> {
+        return self.fields.iter().find(|field| field.key == key);
+    }
+
+    pub fn require(&self, key: &str) -> Result<&Field, String> {
+        return self
+            .get(key)
+            .ok_or_else(|| self.error(&format!("is missing `{}`", key)));
+    }
+
+    pub fn f32_or(&self, key: &str, default: f32) -> Result<f32, String> {
+        return match self.get(key) {
+            Some(field) => field.f32(),
+            None => Ok(default),
+        };
+    }
+
+    pub fn check_keys(&self, allowed: &[&str]) -> Result<(), String> {
+        for field in self.fields.iter() {
+            if !allowed.contains(&field.key.as_str()) {
+                return Err(field.error(&format!(
+                    "unknown key `{}`, expected one of: {}",
+                    field.key,
+                    allowed.join(", ")
+                )));
+            }
+        }
+        return Ok(());
+    }
+}
+
+// parse times like `1:30` into seconds
+pub fn parse_time(text: &str) -> Option<f32> {
+    let (m, s) = text.split_once(':')?;
+    let m: u32 = m.parse().ok()?;
+    let s: u32 = s.parse().ok()?;
+    if s >= 60 {
+        return None;
+    }
+    return Some((m * 60 + s) as f32);
+}
+
+pub fn parse(source: &str) -> Result<Vec<Section>, String> {
+    let mut sections: Vec<Section> = vec![];
+
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let text = text.trim();
+
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        } else if let Some(name) = text.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| format!("line {}: missing `]`", line))?
+                .trim();
+            if let Some(other) = sections.iter().find(|s| s.name == name) {
+                return Err(format!(
+                    "line {}: [{}] is already defined in line {}",
+                    line, name, other.line
+                ));
+            }
+            sections.push(Section {
+                name: name.to_string(),
+                line,
+                fields: vec![],
+            });
+        } else if let Some((key, value)) = text.split_once('=') {
+            let key = key.trim();
+            let section = sections
+                .last_mut()
+                .ok_or_else(|| format!("line {}: `{}` is not inside a [section]", line, key))?;
+            if let Some(other) = section.get(key) {
+                return Err(format!(
+                    "line {}: `{}` is already set in line {}",
+                    line, key, other.line
+                ));
+            }
+            section.fields.push(Field {
+                key: key.to_string(),
+                value: value.trim().to_string(),
+                line,
+            });
+        } else {
+            return Err(format!(
+                "line {}: expected `[name]` or `key = value`, got `{}`",
+                line, text
+            ));
+        }
+    }
+
+    return Ok(sections);
+}
@@ -1,177 +1,606 @@
 // https://vampire-survivors.fandom.com/wiki/Enemies
 
+use crate::defs;
 use crate::game::Pos;
 use crate::sprites;
 
+pub const DEFINITIONS: &str = include_str!("../data/enemies.txt");
+
+const ELITE_HEALTH: f32 = 3.0;
+const ELITE_POWER: f32 = 1.5;
+const FAST_SPEED: f32 = 1.6;
+const ARMORED_HEALTH: f32 = 2.0;
+// share of max health per second
+const REGENERATION: f32 = 0.1;
+// speed multiplier while slowed
+const SLOW: f32 = 0.5;
+// poison stacks with each hit up to this many times
+const POISON_STACKS: u8 = 5;
+// applied for each time the waves have started over
+const LOOP_HEALTH: f32 = 2.0;
+const LOOP_POWER: f32 = 1.5;
+const LOOP_SPEED: f32 = 0.1;
+// speed grows slower than health and power and only up to a limit, so that
+// enemies can still be outrun
+const SPEED_GROWTH: f32 = 0.25;
+const MAX_SPEED_GROWTH: f32 = 1.3;
+
+// multipliers for the stats of newly spawned enemies
+#[derive(Clone, Copy)]
+pub struct Scaling {
+    pub health: f32,
+    pub power: f32,
+    pub speed: f32,
+}
+
+pub struct Difficulty {
+    pub name: &'static str,
+    pub health: f32,
+    pub power: f32,
+    pub speed: f32,
+    // added to the health and power multipliers per minute of the run, the
+    // speed multiplier grows by a quarter of this up to +30%
+    pub growth: f32,
+}
+
+pub const DIFFICULTIES: [Difficulty; 3] = [
+    Difficulty {
+        name: "easy",
+        health: 0.7,
+        power: 0.7,
+        speed: 0.9,
+        growth: 0.02,
+    },
+    Difficulty {
+        name: "normal",
+        health: 1.0,
+        power: 1.0,
+        speed: 1.0,
+        growth: 0.03,
+    },
+    Difficulty {
+        name: "hard",
+        health: 1.5,
+        power: 1.3,
+        speed: 1.1,
+        growth: 0.06,
+    },
+];
+
+impl Difficulty {
+    pub fn find(name: &str) -> Option<&'static Difficulty> {
+        return DIFFICULTIES.iter().find(|d| d.name == name);
+    }
+
+    pub fn scaling(&self, time: f32, loops: usize) -> Scaling {
+        let growth = 1.0 + self.growth * time / 60.0;
+        let speed_growth = (1.0 + SPEED_GROWTH * self.growth * time / 60.0).min(MAX_SPEED_GROWTH);
+        return Scaling {
+            health: self.health * growth * LOOP_HEALTH.powi(loops as i32),
+            power: self.power * growth * LOOP_POWER.powi(loops as i32),
+            speed: self.speed * speed_growth * (1.0 + LOOP_SPEED * loops as f32),
+        };
+    }
+}
+
+const KEYS: [&str; 11] = [
+    "sprite",
+    "speed",
+    "size",
+    "health",
+    "power",
+    "scale",
+    "boss",
+    "attack",
+    "attack_cooldown",
+    "behaviour",
+    "knockback_resistance",
+];
+
+#[derive(Clone, Copy)]
+pub enum Behaviour {
+    // walk towards the player while keeping apart from other enemies
+    Chase,
+    // like chase, but keep this distance to the player
+    Ranged(f32),
+    // stand still for a moment, then run at the player in a straight line
+    Charge,
+    // fly across the screen in a straight line
+    Swarm,
+    // like chase, but split into smaller enemies on death
+    Split(&'static EnemyType, usize),
+    Stationary,
+}
+
+#[derive(Clone, Copy)]
+pub enum Charge {
+    // remaining seconds before the charge starts
+    WindUp(f32),
+    // remaining seconds of the charge
+    Run(f32),
+}
+
+#[derive(Clone, Copy)]
+pub enum Attack {
+    // spawn minions nearby
+    Summon(&'static EnemyType, usize),
+    // fire a projectile at the player
+    Shoot,
+}
+
 pub struct EnemyType {
+    pub name: String,
     pub speed: f32,
     pub size: f32,
     pub health: f32,
     pub power: f32,
     pub sprite: &'static sprites::Sprite,
+    // sprites are drawn this many times larger
+    pub scale: usize,
+    // bosses have a health bar and drop a treasure chest
+    pub boss: bool,
+    pub attack: Option<Attack>,
+    // also used for the charge behaviour
+    pub attack_cooldown: f32,
+    pub behaviour: Behaviour,
+    // 1 means immune to knockback, negative values push further
+    pub knockback_resistance: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Modifier {
+    Fast,
+    Armored,
+    Regenerating,
+    // damages the player when killed nearby
+    Explosive,
+}
+
+pub const MODIFIERS: [Modifier; 4] = [
+    Modifier::Fast,
+    Modifier::Armored,
+    Modifier::Regenerating,
+    Modifier::Explosive,
+];
+
+impl Modifier {
+    pub fn tint(&self) -> [u8; 3] {
+        return match self {
+            Modifier::Fast => [0xff, 0xff, 0x00],
+            Modifier::Armored => [0xcc, 0xcc, 0xcc],
+            Modifier::Regenerating => [0x00, 0xff, 0x66],
+            Modifier::Explosive => [0xff, 0x44, 0x00],
+        };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Effect {
+    Slow,
+    Freeze,
+    // damage over time
+    Burn,
+    // damage over time that stacks
+    Poison,
+}
+
+pub struct Status {
+    pub effect: Effect,
+    // remaining seconds
+    pub duration: f32,
+    // damage per second per stack, for burn and poison
+    pub damage: f32,
+    pub stacks: u8,
 }
 
 pub struct Enemy {
+    pub id: usize,
     pub p: Pos,
     pub health: f32,
+    pub health_max: f32,
+    pub speed: f32,
+    pub power: f32,
     pub t: &'static EnemyType,
+    pub elite: Option<Modifier>,
+    // seconds until the next attack
+    pub cooldown: f32,
+    // unit vector in which swarms and charges move
+    pub dir: Pos,
+    pub charge: Option<Charge>,
+    pub status: Vec<Status>,
+    // time of the last knockback from touching the player
+    pub last_contact: f32,
+}
+
+impl Enemy {
+    pub fn new(
+        id: usize,
+        t: &'static EnemyType,
+        p: Pos,
+        elite: Option<Modifier>,
+        scaling: Scaling,
+    ) -> Self {
+        let mut enemy = Self {
+            id,
+            p,
+            health: t.health * scaling.health,
+            health_max: t.health * scaling.health,
+            speed: t.speed * scaling.speed,
+            power: t.power * scaling.power,
+            t,
+            elite,
+            cooldown: t.attack_cooldown,
+            dir: Pos { x: 0.0, y: 0.0 },
+            charge: None,
+            status: vec![],
+            last_contact: f32::NEG_INFINITY,
+        };
+        if let Some(modifier) = elite {
+            enemy.health_max *= ELITE_HEALTH;
+            enemy.power *= ELITE_POWER;
+            match modifier {
+                Modifier::Fast => enemy.speed *= FAST_SPEED,
+                Modifier::Armored => enemy.health_max *= ARMORED_HEALTH,
+                Modifier::Regenerating | Modifier::Explosive => {}
+            }
+            enemy.health = enemy.health_max;
+        }
+        return enemy;
+    }
+
+    pub fn knock_back(&mut self, from: &Pos, strength: f32) {
+        let dir = from.direction(&self.p);
+        let d = strength * (1.0 - self.t.knockback_resistance);
+        self.p.x += dir.x * d;
+        self.p.y += dir.y * d;
+    }
+
+    // applying an effect again refreshes it
+    pub fn afflict(&mut self, effect: Effect, duration: f32, damage: f32) {
+        match self.status.iter_mut().find(|s| s.effect == effect) {
+            Some(status) => {
+                status.duration = status.duration.max(duration);
+                status.damage = status.damage.max(damage);
+                if effect == Effect::Poison {
+                    status.stacks = (status.stacks + 1).min(POISON_STACKS);
+                }
+            }
+            None => self.status.push(Status {
+                effect,
+                duration,
+                damage,
+                stacks: 1,
+            }),
+        }
+    }
+
+    pub fn update_status(&mut self, dt: f32) {
+        for status in self.status.iter_mut() {
+            if let Effect::Burn | Effect::Poison = status.effect {
+                self.health -= status.damage * status.stacks as f32 * dt;
+            }
+            status.duration -= dt;
+        }
+        self.status.retain(|status| status.duration > 0.0);
+    }
+
+    pub fn has(&self, effect: Effect) -> bool {
+        return self.status.iter().any(|s| s.effect == effect);
+    }
+
+    // speed including status effects
+    pub fn current_speed(&self) -> f32 {
+        if self.has(Effect::Freeze) {
+            return 0.0;
+        } else if self.has(Effect::Slow) {
+            return self.speed * SLOW;
+        } else {
+            return self.speed;
+        }
+    }
+
+    // status effects take precedence over the elite tint
+    pub fn tint(&self) -> Option<[u8; 3]> {
+        for (effect, color) in [
+            (Effect::Freeze, [0xaa, 0xff, 0xff]),
+            (Effect::Burn, [0xff, 0x22, 0x00]),
+            (Effect::Poison, [0xaa, 0x00, 0xff]),
+            (Effect::Slow, [0x00, 0x44, 0xff]),
+        ] {
+            if self.has(effect) {
+                return Some(color);
+            }
+        }
+        return self.elite.map(|modifier| modifier.tint());
+    }
+
+    pub fn regenerate(&mut self, dt: f32) {
+        if self.elite == Some(Modifier::Regenerating) {
+            self.health = (self.health + self.health_max * REGENERATION * dt).min(self.health_max);
+        }
+    }
+}
+
+// projectile fired by an enemy
+pub struct Shot {
+    pub p: Pos,
+    // unit vector
+    pub dir: Pos,
+    pub damage: f32,
+    pub age: f32,
+}
+
+#[derive(Clone, Copy)]
+pub enum Event {
+    // spawn enemies around the edges of the screen
+    Horde(&'static EnemyType, usize),
+    // spawn enemies in a ring around the player
+    Ring(&'static EnemyType, usize),
+    // spawn enemies in a tight formation at the edge of the screen
+    Swarm(&'static EnemyType, usize),
+    Boss(&'static EnemyType),
+}
+
+#[derive(Clone)]
+pub struct Wave {
+    pub start: f32,
+    // enemy types with the average number of spawns per second
+    pub spawns: Vec<(&'static EnemyType, f32)>,
+    pub events: Vec<Event>,
+}
+
+#[derive(Clone)]
+pub struct Timeline {
+    pub waves: Vec<Wave>,
+    // the waves start over after this many seconds
+    pub length: f32,
+    // the run is won after this many seconds
+    pub victory: f32,
+    // the final enemy that needs to be defeated
+    pub reaper: Option<&'static EnemyType>,
+}
+
+impl Timeline {
+    // returns the number of completed loops and the index of the current wave
+    pub fn get_wave(&self, time: f32) -> (usize, usize) {
+        let loops = (time / self.length) as usize;
+        let t = time % self.length;
+        let i = self
+            .waves
+            .iter()
+            .rposition(|wave| wave.start <= t)
+            .unwrap_or(0);
+        return (loops, i);
+    }
+}
+
+// parses `TYPE N`
+fn parse_count(
+    types: &[&'static EnemyType],
+    field: &defs::Field,
+    text: &str,
+    error: impl Fn() -> String,
+) -> Result<(&'static EnemyType, usize), String> {
+    let (name, n) = text.trim().rsplit_once(' ').ok_or_else(&error)?;
+    let n = n.parse().map_err(|_| error())?;
+    return Ok((find_type(types, field, name.trim())?, n));
+}
+
+fn parse_behaviour(types: &[&'static EnemyType], field: &defs::Field) -> Result<Behaviour, String> {
+    let error = || {
+        field.error(&format!(
+            "expected `chase`, `ranged DISTANCE`, `charge`, `swarm`, `split TYPE N`, or `stationary`, got `{}`",
+            field.value
+        ))
+    };
+
+    match field.value.as_str() {
+        "chase" => return Ok(Behaviour::Chase),
+        "charge" => return Ok(Behaviour::Charge),
+        "swarm" => return Ok(Behaviour::Swarm),
+        "stationary" => return Ok(Behaviour::Stationary),
+        _ => {}
+    }
+    if let Some(distance) = field.value.strip_prefix("ranged ") {
+        return Ok(Behaviour::Ranged(
+            distance.trim().parse().map_err(|_| error())?,
+        ));
+    }
+    let rest = field.value.strip_prefix("split ").ok_or_else(error)?;
+    let (t, n) = parse_count(types, field, rest, error)?;
+    return Ok(Behaviour::Split(t, n));
+}
+
+fn parse_attack(types: &[&'static EnemyType], field: &defs::Field) -> Result<Attack, String> {
+    let error = || {
+        field.error(&format!(
+            "expected `shoot` or `summon TYPE N`, got `{}`",
+            field.value
+        ))
+    };
+
+    if field.value == "shoot" {
+        return Ok(Attack::Shoot);
+    }
+    let rest = field.value.strip_prefix("summon ").ok_or_else(error)?;
+    let (t, n) = parse_count(types, field, rest, error)?;
+    return Ok(Attack::Summon(t, n));
+}
+
+fn parse_type(types: &[&'static EnemyType], section: &defs::Section) -> Result<EnemyType, String> {
+    section.check_keys(&KEYS)?;
+
+    let sprite = section.require("sprite")?;
+    let boss = match section.get("boss") {
+        Some(field) => field.bool()?,
+        None => false,
+    };
+    // attacks would otherwise happen on every frame
+    let attack_cooldown = match section.get("attack_cooldown") {
+        Some(field) => {
+            let cooldown = field.f32()?;
+            if cooldown <= 0.0 {
+                return Err(field.error("attack_cooldown must be greater than 0"));
+            }
+            cooldown
+        }
+        None => 5.0,
+    };
+    return Ok(EnemyType {
+        name: section.name.clone(),
+        speed: section.require("speed")?.f32()?,
+        size: section.require("size")?.f32()?,
+        health: section.require("health")?.f32()?,
+        power: section.require("power")?.f32()?,
+        sprite: sprites::by_name(&sprite.value)
+            .ok_or_else(|| sprite.error(&format!("unknown sprite `{}`", sprite.value)))?,
+        scale: match section.get("scale") {
+            Some(field) => field.u8()?.max(1) as usize,
+            None => 1,
+        },
+        boss,
+        attack: match section.get("attack") {
+            Some(field) => Some(parse_attack(types, field)?),
+            None => None,
+        },
+        attack_cooldown,
+        behaviour: match section.get("behaviour") {
+            Some(field) => parse_behaviour(types, field)?,
+            None => Behaviour::Chase,
+        },
+        knockback_resistance: section
+            .f32_or("knockback_resistance", if boss { 1.0 } else { 0.0 })?,
+    });
+}
+
+// enemy types can only refer to types that are defined before them
+fn find_type(
+    types: &[&'static EnemyType],
+    field: &defs::Field,
+    name: &str,
+) -> Result<&'static EnemyType, String> {
+    return types
+        .iter()
+        .find(|t| t.name == name)
+        .copied()
+        .ok_or_else(|| field.error(&format!("unknown enemy type `{}`", name)));
+}
+
+fn parse_spawn(
+    types: &[&'static EnemyType],
+    field: &defs::Field,
+    text: &str,
+) -> Result<(&'static EnemyType, f32), String> {
+    let (name, rate) = text
+        .rsplit_once(' ')
+        .ok_or_else(|| field.error(&format!("expected `TYPE RATE`, got `{}`", text)))?;
+    let rate = rate
+        .parse()
+        .map_err(|_| field.error(&format!("spawn rate must be a number, got `{}`", rate)))?;
+    return Ok((find_type(types, field, name.trim())?, rate));
+}
+
+fn parse_event(
+    types: &[&'static EnemyType],
+    field: &defs::Field,
+    text: &str,
+) -> Result<Event, String> {
+    let error = || {
+        field.error(&format!(
+            "expected `horde TYPE N`, `ring TYPE N`, `swarm TYPE N`, or `boss TYPE`, got `{}`",
+            text
+        ))
+    };
+
+    let (kind, rest) = text.split_once(' ').ok_or_else(error)?;
+    if kind == "boss" {
+        return Ok(Event::Boss(find_type(types, field, rest.trim())?));
+    }
+    let (t, n) = parse_count(types, field, rest, error)?;
+
+    return match kind {
+        "horde" => Ok(Event::Horde(t, n)),
+        "ring" => Ok(Event::Ring(t, n)),
+        "swarm" => Ok(Event::Swarm(t, n)),
+        _ => Err(error()),
+    };
+}
+
+fn parse_wave(
+    types: &[&'static EnemyType],
+    section: &defs::Section,
+    start: f32,
+) -> Result<Wave, String> {
+    section.check_keys(&["spawn", "events"])?;
+
+    let mut wave = Wave {
+        start,
+        spawns: vec![],
+        events: vec![],
+    };
+    if let Some(field) = section.get("spawn") {
+        for text in field.list() {
+            wave.spawns.push(parse_spawn(types, field, text)?);
+        }
+    }
+    if let Some(field) = section.get("events") {
+        for text in field.list() {
+            wave.events.push(parse_event(types, field, text)?);
+        }
+    }
+    return Ok(wave);
 }
 
-const SNAKE: EnemyType = EnemyType {
-    speed: 15.0,
-    size: 8.0,
-    health: 5.0,
-    power: 5.0,
-    sprite: &sprites::SNAKE,
-};
-
-const SKELETON: EnemyType = EnemyType {
-    speed: 15.0,
-    size: 9.0,
-    health: 10.0,
-    power: 10.0,
-    sprite: &sprites::SKELETON,
-};
-
-const SKELETON2: EnemyType = EnemyType {
-    speed: 15.0,
-    size: 9.0,
-    health: 20.0,
-    power: 20.0,
-    sprite: &sprites::SKELETON2,
-};
-
-const BAT: EnemyType = EnemyType {
-    speed: 25.0,
-    size: 8.0,
-    health: 4.0,
-    power: 4.0,
-    sprite: &sprites::BAT,
-};
-
-const BAT2: EnemyType = EnemyType {
-    speed: 25.0,
-    size: 9.0,
-    health: 30.0,
-    power: 5.0,
-    sprite: &sprites::BAT2,
-};
-
-const ZOMBIE: EnemyType = EnemyType {
-    speed: 5.0,
-    size: 9.0,
-    health: 30.0,
-    power: 20.0,
-    sprite: &sprites::ZOMBIE,
-};
-
-const EYE: EnemyType = EnemyType {
-    speed: 13.0,
-    size: 7.0,
-    health: 25.0,
-    power: 25.0,
-    sprite: &sprites::EYE,
-};
-
-const GHOST: EnemyType = EnemyType {
-    speed: 18.0,
-    size: 9.0,
-    health: 30.0,
-    power: 30.0,
-    sprite: &sprites::GHOST,
-};
-
-const MUMMY: EnemyType = EnemyType {
-    speed: 9.0,
-    size: 9.0,
-    health: 40.0,
-    power: 30.0,
-    sprite: &sprites::MUMMY,
-};
-
-const HOOD: EnemyType = EnemyType {
-    speed: 18.0,
-    size: 9.0,
-    health: 40.0,
-    power: 50.0,
-    sprite: &sprites::HOOD,
-};
-
-const HOOD2: EnemyType = EnemyType {
-    speed: 16.0,
-    size: 9.0,
-    health: 60.0,
-    power: 70.0,
-    sprite: &sprites::HOOD2,
-};
-
-const PLANTGUY: EnemyType = EnemyType {
-    speed: 12.0,
-    size: 9.0,
-    health: 60.0,
-    power: 40.0,
-    sprite: &sprites::PLANTGUY,
-};
-
-const RADDISH: EnemyType = EnemyType {
-    speed: 17.0,
-    size: 9.0,
-    health: 40.0,
-    power: 60.0,
-    sprite: &sprites::RADDISH,
-};
-
-const CRAWL: EnemyType = EnemyType {
-    speed: 5.0,
-    size: 9.0,
-    health: 75.0,
-    power: 50.0,
-    sprite: &sprites::CRAWL,
-};
-
-const SHADOW: EnemyType = EnemyType {
-    speed: 22.0,
-    size: 9.0,
-    health: 75.0,
-    power: 75.0,
-    sprite: &sprites::SHADOW,
-};
-
-pub fn get_wave(i: usize) -> Vec<(&'static EnemyType, f32)> {
-    let waves = [
-        vec![(&SNAKE, 2.0)],
-        vec![(&SNAKE, 2.0), (&SKELETON, 2.0)],
-        vec![(&SNAKE, 2.0), (&SKELETON, 2.0)],
-        vec![(&BAT, 4.0)],
-        vec![(&ZOMBIE, 4.0)],
-        vec![(&ZOMBIE, 4.0)],
-        vec![(&BAT, 2.0), (&SKELETON, 2.0)],
-        vec![(&BAT, 2.0), (&SKELETON, 2.0)],
-        vec![(&BAT, 2.0), (&EYE, 2.0)],
-        vec![(&GHOST, 4.0)],
-        vec![(&GHOST, 4.0)],
-        vec![(&BAT2, 2.0), (&ZOMBIE, 2.0)],
-        vec![(&BAT2, 2.0), (&ZOMBIE, 2.0)],
-        vec![(&MUMMY, 2.0), (&ZOMBIE, 2.0)],
-        vec![(&MUMMY, 2.0), (&ZOMBIE, 2.0)],
-        vec![(&HOOD, 4.0)],
-        vec![(&HOOD, 2.0), (&EYE, 2.0)],
-        vec![(&BAT, 10.0)],
-        vec![(&SNAKE, 2.0), (&PLANTGUY, 2.0)],
-        vec![(&SNAKE, 2.0), (&PLANTGUY, 2.0)],
-        vec![(&HOOD2, 4.0)],
-        vec![(&HOOD2, 2.0), (&HOOD, 2.0)],
-        vec![(&HOOD2, 4.0)],
-        vec![(&GHOST, 8.0)],
-        vec![(&SNAKE, 2.0), (&RADDISH, 2.0)],
-        vec![(&RADDISH, 2.0), (&PLANTGUY, 2.0)],
-        vec![(&SKELETON2, 2.0), (&CRAWL, 2.0)],
-        vec![(&SKELETON2, 2.0), (&CRAWL, 2.0)],
-        vec![(&CRAWL, 4.0)],
-        vec![(&SHADOW, 3.0)],
-        vec![(&SHADOW, 4.0), (&CRAWL, 8.0)],
-        vec![(&SHADOW, 5.0)],
-    ];
-
-    return waves[(i / 100) % waves.len()].clone();
+pub fn load(source: &str) -> Result<Timeline, String> {
+    let sections = defs::parse(source)?;
+
+    let mut types = vec![];
+    for section in sections.iter() {
+        if section.name != "settings" && defs::parse_time(&section.name).is_none() {
+            let t = parse_type(&types, section)?;
+            // enemy types are needed for the whole runtime of the game
+            types.push(Box::leak(Box::new(t)) as &'static EnemyType);
+        }
+    }
+
+    let mut waves: Vec<Wave> = vec![];
+    for section in sections.iter() {
+        if let Some(start) = defs::parse_time(&section.name) {
+            if let Some(prev) = waves.last() {
+                if prev.start >= start {
+                    return Err(section.error("waves must be sorted by time"));
+                }
+            }
+            waves.push(parse_wave(&types, section, start)?);
+        }
+    }
+    if waves.first().map(|wave| wave.start) != Some(0.0) {
+        return Err("there must be a wave at [0:00]".to_string());
+    }
+
+    let mut length = waves[waves.len() - 1].start + 30.0;
+    let mut victory = f32::INFINITY;
+    let mut reaper = None;
+    if let Some(section) = sections.iter().find(|s| s.name == "settings") {
+        section.check_keys(&["loop", "victory", "reaper"])?;
+        if let Some(field) = section.get("loop") {
+            length = field.time()?;
+        }
+        if let Some(field) = section.get("victory") {
+            victory = field.time()?;
+        }
+        if let Some(field) = section.get("reaper") {
+            reaper = Some(find_type(&types, field, &field.value)?);
+        }
+    }
+    if length <= waves[waves.len() - 1].start {
+        return Err("`loop` must be after the last wave".to_string());
+    }
+
+    return Ok(Timeline {
+        waves,
+        length,
+        victory,
+        reaper,
+    });
 }
@@ -1,24 +1,43 @@
 use crate::enemies;
+use crate::grid;
+use crate::perks;
 use crate::random;
 use crate::sprites;
 use crate::weapons;
 use crate::win;
+use crate::zones;
 
-const MAX_ENEMIES: usize = 200;
-
-const PERK_POWER: usize = 0;
-const PERK_HEALTH: usize = 1;
-const PERK_SPEED: usize = 2;
-const PERK_RADIUS: usize = 3;
-const PERK_HEAL: usize = 4;
-const PERK_RECOVER: usize = 5;
-const PERK_ATTRACT: usize = 6;
-const PERK_XP: usize = 7;
-const PERK_COOLDOWN: usize = 8;
-const PERK_AXE: usize = 9;
-const PERK_KNIFE: usize = 10;
-const PERK_STAR: usize = 11;
-const PERK_WIND: usize = 12;
+use std::f32::consts::TAU;
+
+// bounds for the adaptive enemy cap
+pub const MIN_ENEMIES: usize = 100;
+pub const MAX_ENEMIES: usize = 5000;
+const DEFAULT_MAX_ENEMIES: usize = 500;
+const CONTACT_KNOCKBACK: f32 = 15.0;
+// seconds between knockbacks from touching the player
+const CONTACT_COOLDOWN: f32 = 0.5;
+// seconds after a hit during which the player cannot be hit again
+const INVINCIBILITY: f32 = 0.5;
+// armor cannot reduce hits below this
+const MIN_DAMAGE: f32 = 1.0;
+// chance for an extra diamond per point of luck
+const LUCKY_DROP: f32 = 0.1;
+const SHAKE_DURATION: f32 = 0.2;
+const SHAKE: f32 = 3.0;
+const ZONE_SPREAD: f32 = 120.0;
+const CHARGE_SPEED: f32 = 4.0;
+const CHARGE_DURATION: f32 = 1.0;
+const WIND_UP: f32 = 0.6;
+const ELITE_CHANCE: f32 = 0.03;
+const ELITE_DIAMONDS: usize = 5;
+const EXPLOSION_RADIUS: f32 = 25.0;
+const SUMMON_RADIUS: f32 = 30.0;
+const SHOT_SPEED: f32 = 50.0;
+const SHOT_SIZE: f32 = 3.0;
+const SHOT_DURATION: f32 = 4.0;
+
+const CHOICES: usize = 3;
+const GRID_SIZE: f32 = 32.0;
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum Dir {
@@ -28,6 +47,26 @@ pub enum Dir {
     Left,
 }
 
+impl Dir {
+    pub fn angle(&self) -> f32 {
+        return match self {
+            Dir::Up => -std::f32::consts::FRAC_PI_2,
+            Dir::Right => 0.0,
+            Dir::Down => std::f32::consts::FRAC_PI_2,
+            Dir::Left => std::f32::consts::PI,
+        };
+    }
+
+    pub fn from_angle(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        if cos.abs() > sin.abs() {
+            return if cos > 0.0 { Dir::Right } else { Dir::Left };
+        } else {
+            return if sin > 0.0 { Dir::Down } else { Dir::Up };
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Pos {
     pub x: f32,
@@ -40,6 +79,21 @@ impl Pos {
         let dy = self.y - other.y;
         return dx * dx + dy * dy < d * d;
     }
+
+    // unit vector pointing to other
+    pub fn direction(&self, other: &Self) -> Pos {
+        let dx = other.x - self.x;
+        let dy = other.y - self.y;
+        let d = (dx * dx + dy * dy).sqrt();
+        if d > 0.0 {
+            return Pos {
+                x: dx / d,
+                y: dy / d,
+            };
+        } else {
+            return Pos { x: 0.0, y: 0.0 };
+        }
+    }
 }
 
 pub struct Player {
@@ -60,10 +114,28 @@ pub struct Player {
     pub cooldown_factor: f32,
     pub last_level: f32,
     pub next_level: f32,
+    pub pending_levels: usize,
+    pub perks: [u8; perks::PERKS.len()],
+    pub banished: [bool; perks::PERKS.len()],
+    pub rerolls: u8,
+    pub skips: u8,
+    pub banishes: u8,
+    // remaining seconds of invincibility
+    pub invincible: f32,
+    // remaining seconds of the red flash after taking damage
+    pub flash: f32,
+    // remaining seconds of screen shake
+    pub shake: f32,
+    // subtracted from the damage of every hit
+    pub armor: f32,
+    // chance to ignore a hit
+    pub evasion: f32,
+    // makes rare perks and extra drops more likely
+    pub luck: f32,
 }
 
 impl Player {
-    pub fn new() -> Self {
+    pub fn new(weapons: Vec<weapons::Weapon>) -> Self {
         return Self {
             p: Pos { x: 0.0, y: 0.0 },
             dir: None,
@@ -74,7 +146,7 @@ impl Player {
             health_max: 100.0,
             health_recover: 0.0,
             power: 6.0,
-            weapons: weapons::create_weapons(),
+            weapons,
             damage_radius: 30.0,
             diamond_radius: 15.0,
             xp: 0.0,
@@ -82,55 +154,136 @@ impl Player {
             cooldown_factor: 1.0,
             last_level: 0.0,
             next_level: 8.0,
+            pending_levels: 0,
+            perks: [0; perks::PERKS.len()],
+            banished: [false; perks::PERKS.len()],
+            rerolls: 3,
+            skips: 3,
+            banishes: 3,
+            invincible: 0.0,
+            flash: 0.0,
+            shake: 0.0,
+            armor: 0.0,
+            evasion: 0.0,
+            luck: 0.0,
         };
     }
 
+    // the shield that damages nearby enemies
+    pub fn aura(&self) -> zones::Zone {
+        return zones::Zone {
+            p: self.p,
+            radius: self.damage_radius,
+            damage: self.power,
+            duration: f32::INFINITY,
+            age: 0.0,
+            color: [0x00, 0xff, 0x00],
+            effect: None,
+        };
+    }
+
+    // returns false if the hit was ignored
+    pub fn hit(&mut self, damage: f32, rng: &mut random::Rng) -> bool {
+        if self.invincible > 0.0 {
+            return false;
+        }
+        self.invincible = INVINCIBILITY;
+        if rng.gen_f32() < self.evasion {
+            return false;
+        }
+        // armor never makes a hit stronger
+        self.health -= (damage - self.armor).max(damage.min(MIN_DAMAGE));
+        self.flash = INVINCIBILITY;
+        self.shake = SHAKE_DURATION;
+        return true;
+    }
+
     pub fn recover(&mut self, dt: f32) {
         self.health = (self.health + self.health_recover * dt).min(self.health_max);
     }
 
-    pub fn levelup(&mut self, rng: &mut random::Rng) {
+    pub fn levelup(&mut self) {
         while self.xp >= self.next_level {
             let current_level = self.next_level;
             self.next_level += (current_level - self.last_level) * 1.2;
             self.last_level = current_level;
+            self.pending_levels += 1;
+        }
+    }
 
-            match rng.gen_range(0, 13) {
-                PERK_POWER => self.power *= 1.1,
-                PERK_HEALTH => self.health_max *= 1.1,
-                PERK_SPEED => self.speed *= 1.1,
-                PERK_RADIUS => self.damage_radius *= 1.1,
-                PERK_HEAL => self.health = self.health_max,
-                PERK_RECOVER => self.health_recover += 0.2,
-                PERK_ATTRACT => self.diamond_radius *= 1.1,
-                PERK_XP => self.xp_factor *= 1.1,
-                PERK_COOLDOWN => self.cooldown_factor *= 0.9,
-                PERK_AXE => self.weapons[0].amount += 1,
-                PERK_KNIFE => self.weapons[1].amount += 1,
-                PERK_STAR => self.weapons[2].amount += 1,
-                PERK_WIND => self.weapons[3].amount += 1,
-                _ => unreachable!(),
-            }
+    pub fn apply_perk(&mut self, perk: usize) {
+        (perks::PERKS[perk].apply)(self);
+        self.perks[perk] += 1;
+    }
+
+    pub fn evolve(&mut self, i: usize) {
+        if let Some(evolution) = &self.weapons[i].evolution {
+            let mut weapon = (*evolution.weapon).clone();
+            weapon.amount = self.weapons[i].amount;
+            weapon.level = self.weapons[i].level;
+            self.weapons[i] = weapon;
         }
     }
 }
 
 pub struct Game {
     pub player: Player,
-    pub diamonds: Vec<Pos>,
+    pub diamonds: grid::Grid<()>,
+    // dropped by bosses, each one is worth a level
+    pub chests: Vec<Pos>,
     pub enemies: Vec<enemies::Enemy>,
+    // indices into enemies, rebuilt when enemies have moved
+    grid: grid::Grid<usize>,
+    // largest enemy size in the grid
+    enemy_size: f32,
     pub i_enemy: usize,
+    // no more enemies spawn once this many are alive
+    pub max_enemies: usize,
+    pub difficulty: &'static enemies::Difficulty,
+    pub screen_shake: bool,
+    // seconds since the start of the run
+    pub time: f32,
+    // loop count and index of the current wave
+    pub wave: Option<(usize, usize)>,
+    pub timeline: enemies::Timeline,
+    // id of the reaper once it has appeared
+    pub reaper: Option<usize>,
+    pub won: bool,
+    pub bolts: Vec<weapons::Bolt>,
+    pub shots: Vec<enemies::Shot>,
+    pub zones: Vec<zones::Zone>,
+    pub choice: Vec<perks::Choice>,
+    pub banishing: bool,
     rng: random::Rng,
+    // used for visual effects only, so that they do not change the game
+    effects_rng: random::Rng,
 }
 
 impl Game {
-    pub fn new() -> Self {
+    pub fn new(weapons: Vec<weapons::Weapon>, timeline: enemies::Timeline) -> Self {
         return Self {
             enemies: vec![],
-            diamonds: vec![],
+            grid: grid::Grid::new(GRID_SIZE),
+            enemy_size: 0.0,
+            diamonds: grid::Grid::new(GRID_SIZE),
+            chests: vec![],
             i_enemy: 0,
-            player: Player::new(),
+            max_enemies: DEFAULT_MAX_ENEMIES,
+            difficulty: &enemies::DIFFICULTIES[1],
+            screen_shake: false,
+            time: 0.0,
+            wave: None,
+            timeline,
+            reaper: None,
+            won: false,
+            bolts: vec![],
+            shots: vec![],
+            zones: vec![],
+            choice: vec![],
+            banishing: false,
+            player: Player::new(weapons),
             rng: random::Rng::new(),
+            effects_rng: random::Rng::new(),
         };
     }
 
@@ -144,73 +297,291 @@ impl Game {
         };
     }
 
-    fn move_enemies(&mut self, dt: f32) {
-        for i in 0..self.enemies.len() {
-            let enemy = &self.enemies[i];
+    // unit vector towards the player, pushed away from nearby enemies
+    fn chase(&self, i: usize, range: f32) -> Pos {
+        let enemy = &self.enemies[i];
 
-            let dxp = self.player.p.x - enemy.p.x;
-            let dyp = self.player.p.y - enemy.p.y;
-            let dp = (dxp * dxp + dyp * dyp).sqrt();
+        let dxp = self.player.p.x - enemy.p.x;
+        let dyp = self.player.p.y - enemy.p.y;
+        let dp = (dxp * dxp + dyp * dyp).sqrt();
 
-            let mut dx = dxp / dp;
-            let mut dy = dyp / dp;
+        let mut dx = dxp / dp;
+        let mut dy = dyp / dp;
 
-            for j in 0..self.enemies.len() {
-                if i != j {
-                    let other = &self.enemies[j];
+        // ranged enemies back off when the player comes too close
+        if dp < range * 0.8 {
+            dx = -dx;
+            dy = -dy;
+        } else if dp < range {
+            dx = 0.0;
+            dy = 0.0;
+        }
 
-                    let dxm = other.p.x - enemy.p.x;
-                    let dym = other.p.y - enemy.p.y;
-                    let dm = (dxm * dxm + dym * dym).sqrt();
+        for &(_, j) in self.grid.nearby(&enemy.p, enemy.t.size + self.enemy_size) {
+            if i != j {
+                let other = &self.enemies[j];
 
-                    if dm < enemy.t.size + other.t.size {
-                        dx -= dxm / dm;
-                        dy -= dym / dm;
-                    }
+                let dxm = other.p.x - enemy.p.x;
+                let dym = other.p.y - enemy.p.y;
+                let dm = (dxm * dxm + dym * dym).sqrt();
+
+                if dm < enemy.t.size + other.t.size {
+                    dx -= dxm / dm;
+                    dy -= dym / dm;
                 }
             }
+        }
+
+        let d = (dx * dx + dy * dy).sqrt();
+        if d > 0.0 {
+            return Pos {
+                x: dx / d,
+                y: dy / d,
+            };
+        } else {
+            return Pos { x: 0.0, y: 0.0 };
+        }
+    }
 
-            let d = (dx * dx + dy * dy).sqrt();
-            dx /= d;
-            dy /= d;
+    fn build_grid(&mut self) {
+        self.grid.clear();
+        self.enemy_size = 0.0;
+        for (i, enemy) in self.enemies.iter().enumerate() {
+            self.grid.insert(enemy.p, i);
+            self.enemy_size = self.enemy_size.max(enemy.t.size);
+        }
+    }
+
+    fn move_enemies(&mut self, dt: f32) {
+        self.build_grid();
+        for i in 0..self.enemies.len() {
+            let enemy = &self.enemies[i];
+            let speed = enemy.current_speed();
+            let (dir, speed) = match enemy.t.behaviour {
+                enemies::Behaviour::Chase | enemies::Behaviour::Split(..) => {
+                    (self.chase(i, 0.0), speed)
+                }
+                enemies::Behaviour::Ranged(range) => (self.chase(i, range), speed),
+                enemies::Behaviour::Charge => match enemy.charge {
+                    Some(enemies::Charge::WindUp(_)) => continue,
+                    Some(enemies::Charge::Run(_)) => (enemy.dir, speed * CHARGE_SPEED),
+                    None => (self.chase(i, 0.0), speed),
+                },
+                enemies::Behaviour::Swarm => (enemy.dir, speed),
+                enemies::Behaviour::Stationary => continue,
+            };
 
             let enemy = &mut self.enemies[i];
-            enemy.p.x += dx * enemy.t.speed * dt;
-            enemy.p.y += dy * enemy.t.speed * dt;
+            enemy.p.x += dir.x * speed * dt;
+            enemy.p.y += dir.y * speed * dt;
         }
     }
 
     fn move_projectiles(&mut self, dt: f32) {
+        let ctx = weapons::Context {
+            center: &self.player.p,
+            enemies: &self.enemies,
+        };
         for weapon in self.player.weapons.iter_mut() {
+            let _move = match weapon.kind {
+                weapons::Kind::Projectile(_move) => _move,
+                weapons::Kind::Orbit => weapons::move_orbit,
+                _ => continue,
+            };
             for projectile in weapon.projectiles.iter_mut() {
-                (weapon._move)(projectile, &self.player.p, weapon.speed, dt);
+                projectile.age += dt;
+                _move(projectile, &ctx, weapon.speed, dt);
             }
         }
+
+        for bolt in self.bolts.iter_mut() {
+            bolt.age += dt;
+        }
+        self.bolts.retain(|bolt| bolt.age < weapons::BOLT_DURATION);
+
+        for shot in self.shots.iter_mut() {
+            shot.age += dt;
+            shot.p.x += shot.dir.x * SHOT_SPEED * dt;
+            shot.p.y += shot.dir.y * SHOT_SPEED * dt;
+        }
     }
 
-    fn spawn_enemies(&mut self, dt: f32, width: f32, height: f32) {
+    // enemies get tougher over time and with every loop of the waves
+    pub fn scaling(&self) -> enemies::Scaling {
+        let (loops, _) = self.timeline.get_wave(self.time);
+        return self.difficulty.scaling(self.time, loops);
+    }
+
+    fn spawn_enemy(
+        &mut self,
+        t: &'static enemies::EnemyType,
+        p: Pos,
+        elite: Option<enemies::Modifier>,
+    ) {
+        // the reaper is meant to be a fixed final challenge
+        let scaling = if self.reaper == Some(self.i_enemy) {
+            self.difficulty.scaling(0.0, 0)
+        } else {
+            self.scaling()
+        };
+        let mut enemy = enemies::Enemy::new(self.i_enemy, t, p, elite, scaling);
+        enemy.dir = p.direction(&self.player.p);
+        self.enemies.push(enemy);
+        self.i_enemy += 1;
+    }
+
+    // random position just outside of the screen
+    fn edge_position(&mut self, width: f32, height: f32) -> Pos {
         let sprite_height = win::iconvert_y(sprites::HEIGHT);
         let sprite_width = win::iconvert_x(sprites::WIDTH);
 
-        for (t, p) in enemies::get_wave(self.i_enemy) {
-            if self.enemies.len() < MAX_ENEMIES && self.rng.gen_f32() < dt * p {
-                let (spawn_x, spawn_y) = match self.rng.gen_range(0, 4) {
-                    0 => (self.rng.gen_f32() * width, -sprite_height),
-                    1 => (width + sprite_width, self.rng.gen_f32() * height),
-                    2 => (self.rng.gen_f32() * width, height + sprite_height),
-                    3 => (-sprite_width, self.rng.gen_f32() * height),
-                    _ => unreachable!(),
-                };
+        let (spawn_x, spawn_y) = match self.rng.gen_range(0, 4) {
+            0 => (self.rng.gen_f32() * width, -sprite_height),
+            1 => (width + sprite_width, self.rng.gen_f32() * height),
+            2 => (self.rng.gen_f32() * width, height + sprite_height),
+            3 => (-sprite_width, self.rng.gen_f32() * height),
+            _ => unreachable!(),
+        };
+
+        return Pos {
+            x: spawn_x + self.player.p.x - width / 2.0,
+            y: spawn_y + self.player.p.y - height / 2.0,
+        };
+    }
+
+    fn start_event(&mut self, event: enemies::Event, width: f32, height: f32) {
+        match event {
+            enemies::Event::Horde(t, n) => {
+                for _ in 0..n {
+                    let p = self.edge_position(width, height);
+                    self.spawn_enemy(t, p, None);
+                }
+            }
+            enemies::Event::Ring(t, n) => {
+                for i in 0..n {
+                    let (sin, cos) = (i as f32 * TAU / n as f32).sin_cos();
+                    let p = Pos {
+                        x: self.player.p.x + cos * width * 0.6,
+                        y: self.player.p.y + sin * height * 0.6,
+                    };
+                    self.spawn_enemy(t, p, None);
+                }
+            }
+            enemies::Event::Swarm(t, n) => {
+                let center = self.edge_position(width, height);
+                let side = (n as f32).sqrt().ceil() as usize;
+                let spacing = t.size * 2.0;
+                // the whole formation flies in parallel instead of converging
+                let dir = center.direction(&self.player.p);
+                for i in 0..n {
+                    let p = Pos {
+                        x: center.x + ((i % side) as f32 - side as f32 / 2.0) * spacing,
+                        y: center.y + ((i / side) as f32 - side as f32 / 2.0) * spacing,
+                    };
+                    self.spawn_enemy(t, p, None);
+                    if let Some(enemy) = self.enemies.last_mut() {
+                        enemy.dir = dir;
+                    }
+                }
+            }
+            enemies::Event::Boss(t) => {
+                let p = self.edge_position(width, height);
+                self.spawn_enemy(t, p, None);
+            }
+        }
+    }
+
+    fn enemy_attacks(&mut self, dt: f32) {
+        let mut summons = vec![];
+        for enemy in self.enemies.iter_mut() {
+            enemy.charge = match enemy.charge {
+                Some(enemies::Charge::WindUp(time)) if time <= dt => {
+                    enemy.dir = enemy.p.direction(&self.player.p);
+                    Some(enemies::Charge::Run(CHARGE_DURATION))
+                }
+                Some(enemies::Charge::WindUp(time)) => Some(enemies::Charge::WindUp(time - dt)),
+                Some(enemies::Charge::Run(time)) if time <= dt => None,
+                Some(enemies::Charge::Run(time)) => Some(enemies::Charge::Run(time - dt)),
+                None => None,
+            };
+
+            let charges = matches!(enemy.t.behaviour, enemies::Behaviour::Charge);
+            if enemy.t.attack.is_none() && !charges {
+                continue;
+            }
+            enemy.cooldown -= dt;
+            if enemy.cooldown > 0.0 {
+                continue;
+            }
+            enemy.cooldown += enemy.t.attack_cooldown;
+
+            if charges && enemy.charge.is_none() {
+                enemy.charge = Some(enemies::Charge::WindUp(WIND_UP));
+            }
+            match enemy.t.attack {
+                Some(enemies::Attack::Summon(t, n)) => summons.push((t, n, enemy.p, SUMMON_RADIUS)),
+                Some(enemies::Attack::Shoot) => self.shots.push(enemies::Shot {
+                    p: enemy.p,
+                    dir: enemy.p.direction(&self.player.p),
+                    damage: enemy.power,
+                    age: 0.0,
+                }),
+                None => {}
+            }
+        }
+
+        for (t, n, center, radius) in summons {
+            // summons count towards the enemy cap like regular spawns
+            let n = n.min(self.max_enemies.saturating_sub(self.enemies.len()));
+            self.spawn_around(t, n, center, radius);
+        }
+    }
+
+    fn spawn_around(&mut self, t: &'static enemies::EnemyType, n: usize, center: Pos, radius: f32) {
+        for i in 0..n {
+            let (sin, cos) = (i as f32 * TAU / n as f32).sin_cos();
+            let p = Pos {
+                x: center.x + cos * radius,
+                y: center.y + sin * radius,
+            };
+            self.spawn_enemy(t, p, None);
+        }
+    }
+
+    fn spawn_enemies(&mut self, dt: f32, width: f32, height: f32) {
+        if self.time >= self.timeline.victory {
+            if self.reaper.is_none() {
+                match self.timeline.reaper {
+                    Some(t) => {
+                        self.enemies.clear();
+                        self.reaper = Some(self.i_enemy);
+                        let p = self.edge_position(width, height);
+                        self.spawn_enemy(t, p, None);
+                    }
+                    None => self.won = true,
+                }
+            }
+            return;
+        }
+
+        let wave = self.timeline.get_wave(self.time);
+        let i = wave.1;
+        if self.wave != Some(wave) {
+            self.wave = Some(wave);
+            for event in self.timeline.waves[i].events.clone() {
+                self.start_event(event, width, height);
+            }
+        }
 
-                self.enemies.push(enemies::Enemy {
-                    p: Pos {
-                        x: spawn_x + self.player.p.x - width / 2.0,
-                        y: spawn_y + self.player.p.y - height / 2.0,
-                    },
-                    health: t.health,
-                    t: t,
-                });
-                self.i_enemy += 1;
+        for (t, p) in self.timeline.waves[i].spawns.clone() {
+            if self.enemies.len() < self.max_enemies && self.rng.gen_f32() < dt * p {
+                let p = self.edge_position(width, height);
+                let elite = if self.rng.gen_f32() < ELITE_CHANCE {
+                    Some(enemies::MODIFIERS[self.rng.gen_range(0, enemies::MODIFIERS.len())])
+                } else {
+                    None
+                };
+                self.spawn_enemy(t, p, elite);
             }
         }
     }
@@ -219,7 +590,10 @@ impl Game {
         self.enemies = std::mem::take(&mut self.enemies)
             .into_iter()
             .filter(|e| {
-                (e.p.y - self.player.p.y).abs() < height && (e.p.x - self.player.p.x).abs() < width
+                self.reaper == Some(e.id)
+                    || e.t.boss
+                    || (e.p.y - self.player.p.y).abs() < height
+                        && (e.p.x - self.player.p.x).abs() < width
             })
             .collect();
     }
@@ -229,17 +603,78 @@ impl Game {
             weapon.last += dt;
             if weapon.last > weapon.cooldown * self.player.cooldown_factor {
                 weapon.last -= weapon.cooldown * self.player.cooldown_factor;
-                for _ in 0..weapon.amount {
-                    weapon.projectiles.push(weapons::Projectile {
-                        p: Pos {
-                            x: self.player.p.x + (self.rng.gen_f32() - 0.5) * weapons::SPAWN_RADIUS,
-                            y: self.player.p.y + (self.rng.gen_f32() - 0.5) * weapons::SPAWN_RADIUS,
-                        },
-                        dir: match &self.player.dir {
-                            Some(dir) => dir.clone(),
-                            None => self.player.face,
-                        },
-                    });
+
+                let dir = match &self.player.dir {
+                    Some(dir) => *dir,
+                    None => self.player.face,
+                };
+
+                match weapon.kind {
+                    weapons::Kind::Projectile(_) | weapons::Kind::Orbit => {
+                        let orbit = matches!(weapon.kind, weapons::Kind::Orbit);
+                        for i in 0..weapon.amount {
+                            // spread evenly so orbiting projectiles do not overlap
+                            let angle = if orbit {
+                                dir.angle() + i as f32 * TAU / weapon.amount as f32
+                            } else {
+                                dir.angle()
+                            };
+                            weapon.projectiles.push(weapons::Projectile {
+                                p: Pos {
+                                    x: self.player.p.x
+                                        + (self.rng.gen_f32() - 0.5) * weapons::SPAWN_RADIUS,
+                                    y: self.player.p.y
+                                        + (self.rng.gen_f32() - 0.5) * weapons::SPAWN_RADIUS,
+                                },
+                                dir,
+                                angle,
+                                age: 0.0,
+                                pierce: weapon.pierce,
+                                hits: vec![],
+                            });
+                        }
+                    }
+                    weapons::Kind::Chain { jumps, radius } => {
+                        let mut struck = vec![];
+                        for _ in 0..weapon.amount {
+                            weapons::strike_chain(
+                                &mut self.enemies,
+                                &self.player.p,
+                                jumps,
+                                radius,
+                                weapon.damage * self.player.power,
+                                &mut struck,
+                                &mut self.bolts,
+                            );
+                        }
+                        if let Some((effect, duration)) = weapon.effect {
+                            for enemy in self.enemies.iter_mut() {
+                                if struck.contains(&enemy.id) {
+                                    enemy.afflict(
+                                        effect,
+                                        duration,
+                                        weapon.damage * self.player.power,
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    weapons::Kind::Zone => {
+                        for _ in 0..weapon.amount {
+                            self.zones.push(zones::Zone {
+                                p: Pos {
+                                    x: self.player.p.x + (self.rng.gen_f32() - 0.5) * ZONE_SPREAD,
+                                    y: self.player.p.y + (self.rng.gen_f32() - 0.5) * ZONE_SPREAD,
+                                },
+                                radius: weapon.size,
+                                damage: weapon.damage * self.player.power,
+                                duration: weapon.duration,
+                                age: 0.0,
+                                color: [0x44, 0x88, 0xff],
+                                effect: weapon.effect,
+                            });
+                        }
+                    }
                 }
             }
         }
@@ -247,18 +682,57 @@ impl Game {
 
     fn despawn_projectiles(&mut self, width: f32, height: f32) {
         for weapon in self.player.weapons.iter_mut() {
+            // an orbit ends before the next one starts so that rings do not stack
+            let duration = match weapon.kind {
+                weapons::Kind::Orbit => weapon
+                    .duration
+                    .min(weapon.cooldown * self.player.cooldown_factor),
+                _ => weapon.duration,
+            };
             weapon.projectiles = std::mem::take(&mut weapon.projectiles)
                 .into_iter()
                 .filter(|proj| {
-                    (proj.p.y - self.player.p.y).abs() < height
+                    !proj.spent()
+                        && proj.age < duration
+                        && (proj.p.y - self.player.p.y).abs() < height
                         && (proj.p.x - self.player.p.x).abs() < width
                 })
                 .collect();
         }
+
+        self.shots.retain(|shot| {
+            shot.age < SHOT_DURATION
+                && (shot.p.y - self.player.p.y).abs() < height
+                && (shot.p.x - self.player.p.x).abs() < width
+        });
+    }
+
+    fn update_zones(&mut self, dt: f32) {
+        for zone in self.zones.iter_mut() {
+            zone.age += dt;
+        }
+        self.zones.retain(|zone| !zone.expired());
     }
 
     fn apply_damage(&mut self, dt: f32) {
-        for enemy in self.enemies.iter_mut() {
+        let player = &mut self.player;
+        let rng = &mut self.rng;
+        self.shots.retain(|shot| {
+            if shot.p.in_radius(&player.p, player.size + SHOT_SIZE) {
+                player.hit(shot.damage, rng);
+                return false;
+            } else {
+                return true;
+            }
+        });
+
+        self.build_grid();
+
+        // one hit from the strongest enemy touching the player
+        let mut contact: f32 = 0.0;
+        let radius = self.player.size + self.enemy_size;
+        for &(_, i) in self.grid.nearby(&self.player.p, radius) {
+            let enemy = &mut self.enemies[i];
             let dx = self.player.p.x - enemy.p.x;
             let dy = self.player.p.y - enemy.p.y;
             let dx2 = dx * dx;
@@ -266,93 +740,285 @@ impl Game {
 
             let size = enemy.t.size + self.player.size;
             if dx2 + dy2 * 4.0 < size * size {
-                self.player.health -= enemy.t.power * dt;
-
-                let d = (dx2 + dy2).sqrt();
-                enemy.p.x -= dx / d * 3.0;
-                enemy.p.y -= dy / d * 3.0;
+                contact = contact.max(enemy.power);
+                if self.time - enemy.last_contact >= CONTACT_COOLDOWN {
+                    enemy.last_contact = self.time;
+                    enemy.knock_back(&self.player.p, CONTACT_KNOCKBACK);
+                }
             }
+        }
+        if contact > 0.0 {
+            // power is damage per second while touching
+            self.player.hit(contact * INVINCIBILITY, &mut self.rng);
+        }
+        // knockback moved enemies out of their cells
+        self.build_grid();
 
-            if dx2 + dy2 < self.player.damage_radius * self.player.damage_radius {
-                enemy.health -= self.player.power * dt;
+        let aura = self.player.aura();
+        for zone in std::iter::once(&aura).chain(self.zones.iter()) {
+            for &(_, i) in self.grid.nearby(&zone.p, zone.radius) {
+                zone.apply(&mut self.enemies[i], dt);
             }
-            for weapon in self.player.weapons.iter() {
-                for projectile in weapon.projectiles.iter() {
+        }
+
+        // applied after all hits so that the grid stays valid for the lookups
+        let mut knockbacks = vec![];
+        for weapon in self.player.weapons.iter_mut() {
+            for projectile in weapon.projectiles.iter_mut() {
+                let radius = weapon.size + self.enemy_size;
+                for &(_, i) in self.grid.nearby(&projectile.p, radius) {
+                    let enemy = &mut self.enemies[i];
                     let projectile_size = enemy.t.size + weapon.size;
-                    if projectile.p.in_radius(&enemy.p, projectile_size) {
-                        enemy.health -= weapon.damage * self.player.power * dt;
-
-                        let dx = projectile.p.x - enemy.p.x;
-                        let dy = projectile.p.y - enemy.p.y;
-                        let d = (dx * dx + dy * dy).sqrt();
-                        enemy.p.x -= dx / d * 3.0;
-                        enemy.p.y -= dy / d * 3.0;
+                    if projectile.p.in_radius(&enemy.p, projectile_size)
+                        && projectile.can_hit(enemy.id, weapon.hit_cooldown)
+                    {
+                        projectile.hit(enemy.id, weapon.hit_cooldown);
+                        enemy.health -= weapon.damage * self.player.power;
+                        if let Some((effect, duration)) = weapon.effect {
+                            enemy.afflict(effect, duration, weapon.damage * self.player.power);
+                        }
+                        knockbacks.push((i, projectile.p, weapon.knockback));
                     }
                 }
             }
         }
+        for (i, from, strength) in knockbacks {
+            self.enemies[i].knock_back(&from, strength);
+        }
 
+        let mut splits = vec![];
         self.enemies = std::mem::take(&mut self.enemies)
             .into_iter()
             .filter(|enemy| {
                 if enemy.health <= 0.0 {
-                    self.diamonds.push(Pos {
-                        x: enemy.p.x,
-                        y: enemy.p.y,
-                    });
+                    if let enemies::Behaviour::Split(t, n) = enemy.t.behaviour {
+                        splits.push((t, n, enemy.p, enemy.t.size));
+                    }
+                    if enemy.elite == Some(enemies::Modifier::Explosive) {
+                        if self.player.p.in_radius(&enemy.p, EXPLOSION_RADIUS) {
+                            self.player.hit(enemy.power, &mut self.rng);
+                        }
+                        self.zones.push(zones::Zone {
+                            p: enemy.p,
+                            radius: EXPLOSION_RADIUS,
+                            damage: 0.0,
+                            duration: 0.3,
+                            age: 0.0,
+                            color: [0xff, 0x66, 0x00],
+                            effect: None,
+                        });
+                    }
+
+                    if enemy.t.boss {
+                        self.chests.push(enemy.p);
+                    } else if enemy.elite.is_some() {
+                        for _ in 0..ELITE_DIAMONDS {
+                            let p = Pos {
+                                x: enemy.p.x + (self.rng.gen_f32() - 0.5) * enemy.t.size * 2.0,
+                                y: enemy.p.y + (self.rng.gen_f32() - 0.5) * enemy.t.size * 2.0,
+                            };
+                            self.diamonds.insert(p, ());
+                        }
+                    } else {
+                        self.diamonds.insert(enemy.p, ());
+                        if self.rng.gen_f32() < self.player.luck * LUCKY_DROP {
+                            let p = Pos {
+                                x: enemy.p.x + enemy.t.size,
+                                y: enemy.p.y,
+                            };
+                            self.diamonds.insert(p, ());
+                        }
+                    }
                     return false;
                 } else {
                     return true;
                 }
             })
             .collect();
+
+        for (t, n, center, radius) in splits {
+            // split enemies count towards the enemy cap like regular spawns
+            let n = n.min(self.max_enemies.saturating_sub(self.enemies.len()));
+            self.spawn_around(t, n, center, radius);
+        }
     }
 
     fn pick_diamonds(&mut self) {
-        self.diamonds = std::mem::take(&mut self.diamonds)
-            .into_iter()
-            .filter(|diamond| {
-                if self.player.p.in_radius(&diamond, self.player.diamond_radius) {
-                    self.player.xp += self.player.xp_factor;
-                    return false;
-                } else {
-                    return true;
+        let player = &mut self.player;
+        let (p, radius) = (player.p, player.diamond_radius);
+        self.diamonds.retain_nearby(&p, radius, |diamond, _| {
+            if p.in_radius(diamond, radius) {
+                player.xp += player.xp_factor;
+                return false;
+            } else {
+                return true;
+            }
+        });
+    }
+
+    fn pick_chests(&mut self) {
+        let (p, radius) = (self.player.p, self.player.diamond_radius);
+        let before = self.chests.len();
+        self.chests.retain(|chest| !p.in_radius(chest, radius));
+        self.player.pending_levels += before - self.chests.len();
+    }
+
+    fn offer_perks(&mut self) {
+        if self.choice.is_empty() && self.player.pending_levels > 0 {
+            for i in 0..self.player.weapons.len() {
+                if self.choice.len() < CHOICES && perks::can_evolve(&self.player, i) {
+                    self.choice.push(perks::Choice::Evolution(i));
                 }
-            })
-            .collect();
+            }
+
+            let mut pool = perks::pool(&self.player);
+            while self.choice.len() < CHOICES && !pool.is_empty() {
+                let weights: Vec<f32> = pool.iter().map(|(_, weight)| *weight).collect();
+                let i = self.rng.gen_weighted(&weights);
+                self.choice.push(pool.swap_remove(i).0);
+            }
+            if self.choice.is_empty() {
+                // nothing left to offer
+                self.player.pending_levels = 0;
+            }
+        }
+    }
+
+    pub fn choose(&mut self, i: usize) {
+        match (self.choice.get(i), self.banishing) {
+            (Some(&perks::Choice::Perk(perk)), true) => {
+                self.player.banished[perk] = true;
+                self.player.banishes -= 1;
+                self.banishing = false;
+            }
+            (Some(&perks::Choice::Weapon(weapon)), true) => {
+                self.player.weapons[weapon].banished = true;
+                self.player.banishes -= 1;
+                self.banishing = false;
+            }
+            (Some(&perks::Choice::Perk(perk)), false) => {
+                self.player.apply_perk(perk);
+                self.player.pending_levels -= 1;
+            }
+            (Some(&perks::Choice::Weapon(weapon)), false) => {
+                self.player.weapons[weapon].upgrade();
+                self.player.pending_levels -= 1;
+            }
+            (Some(&perks::Choice::Evolution(weapon)), false) => {
+                self.player.evolve(weapon);
+                self.player.pending_levels -= 1;
+            }
+            // evolutions cannot be banished
+            _ => return,
+        }
+        self.choice.clear();
+        self.offer_perks();
+    }
+
+    pub fn reroll(&mut self) {
+        if self.paused() && self.player.rerolls > 0 {
+            self.player.rerolls -= 1;
+            self.banishing = false;
+            self.choice.clear();
+            self.offer_perks();
+        }
+    }
+
+    pub fn skip(&mut self) {
+        if self.paused() && self.player.skips > 0 {
+            self.player.skips -= 1;
+            self.player.pending_levels -= 1;
+            self.banishing = false;
+            self.choice.clear();
+            self.offer_perks();
+        }
+    }
+
+    pub fn toggle_banish(&mut self) {
+        if self.paused() && self.player.banishes > 0 {
+            self.banishing = !self.banishing;
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        return !self.choice.is_empty();
     }
 
     pub fn step(&mut self, dt: f32, width: f32, height: f32) {
+        if self.paused() {
+            return;
+        }
+
+        self.time += dt;
         self.move_player(dt);
+        self.enemy_attacks(dt);
         self.move_enemies(dt);
+        for enemy in self.enemies.iter_mut() {
+            enemy.regenerate(dt);
+            enemy.update_status(dt);
+        }
         self.move_projectiles(dt);
+        self.update_zones(dt);
         self.despawn_enemies(width, height);
         self.despawn_projectiles(width, height);
 
         self.apply_damage(dt);
         self.pick_diamonds();
+        self.pick_chests();
+
+        if let Some(id) = self.reaper {
+            if !self.enemies.iter().any(|e| e.id == id) {
+                self.won = true;
+            }
+        }
 
+        self.player.invincible = (self.player.invincible - dt).max(0.0);
+        self.player.flash = (self.player.flash - dt).max(0.0);
+        self.player.shake = (self.player.shake - dt).max(0.0);
         self.player.recover(dt);
-        self.player.levelup(&mut self.rng);
+        self.player.levelup();
         self.spawn_enemies(dt, width, height);
         self.spawn_projectiles(dt);
+        self.offer_perks();
+    }
+
+    fn render_player(&self, win: &mut win::Window, x: f32, y: f32) {
+        // flash red after taking damage, evaded hits do not flash
+        let flash = self.player.flash > 0.0 && (self.player.flash * 10.0) as i32 % 2 == 0;
+        win.scaled_sprite(
+            x,
+            y,
+            &sprites::PLAYER,
+            self.player.face,
+            1,
+            if flash {
+                Some([0xff, 0x00, 0x00])
+            } else {
+                None
+            },
+        );
     }
 
     pub fn render(&mut self, win: &mut win::Window) {
         let height = win::iconvert_y(win.height);
         let width = win::iconvert_x(win.width);
-        let dx = width / 2.0 - self.player.p.x;
-        let dy = height / 2.0 - self.player.p.y;
+
+        let mut cx = width / 2.0;
+        let mut cy = height / 2.0;
+        if self.screen_shake && self.player.shake > 0.0 {
+            cx += (self.effects_rng.gen_f32() - 0.5) * SHAKE * 2.0;
+            cy += (self.effects_rng.gen_f32() - 0.5) * SHAKE * 2.0;
+        }
+        let dx = cx - self.player.p.x;
+        let dy = cy - self.player.p.y;
 
         win.fill([0x33, 0x88, 0x22]);
-        win.circle(
-            width / 2.0,
-            height / 2.0,
-            self.player.damage_radius,
-            [0x00, 0xff, 0x00],
-        );
+        for zone in std::iter::once(&self.player.aura()).chain(self.zones.iter()) {
+            win.circle(zone.p.x + dx, zone.p.y + dy, zone.radius, zone.color);
+        }
 
-        for diamond in self.diamonds.iter() {
+        let radius = width.max(height) / 2.0 + win::iconvert_y(sprites::HEIGHT);
+        for (diamond, _) in self.diamonds.nearby(&self.player.p, radius) {
             win.sprite(
                 diamond.x + dx,
                 diamond.y + dy,
@@ -360,22 +1026,26 @@ impl Game {
                 Dir::Right,
             );
         }
+        for chest in self.chests.iter() {
+            win.sprite(chest.x + dx, chest.y + dy, &sprites::CHEST, Dir::Right);
+        }
 
         let mut player_rendered = false;
         self.enemies.sort_unstable_by_key(|e| e.p.y as i32);
         for enemy in self.enemies.iter() {
             if !player_rendered && enemy.p.y > self.player.p.y {
-                win.sprite(
-                    width / 2.0,
-                    height / 2.0,
-                    &sprites::PLAYER,
-                    self.player.face,
-                );
+                self.render_player(win, cx, cy);
                 player_rendered = true;
             }
 
-            win.sprite(
-                enemy.p.x + dx,
+            // shake while winding up a charge
+            let shake = match enemy.charge {
+                Some(enemies::Charge::WindUp(time)) => ((time * 20.0) as i32 % 2 * 2 - 1) as f32,
+                _ => 0.0,
+            };
+
+            win.scaled_sprite(
+                enemy.p.x + dx + shake,
                 enemy.p.y + dy,
                 enemy.t.sprite,
                 if enemy.p.x > self.player.p.x {
@@ -383,26 +1053,39 @@ impl Game {
                 } else {
                     Dir::Right
                 },
+                enemy.t.scale,
+                enemy.tint(),
             );
         }
         if !player_rendered {
-            win.sprite(
-                width / 2.0,
-                height / 2.0,
-                &sprites::PLAYER,
-                self.player.face,
-            );
+            self.render_player(win, cx, cy);
         }
 
         for weapon in self.player.weapons.iter() {
-            for projectile in weapon.projectiles.iter() {
-                win.sprite(
-                    projectile.p.x + dx,
-                    projectile.p.y + dy,
-                    weapon.sprite,
-                    projectile.dir,
-                );
+            if let Some(sprite) = weapon.sprite {
+                for projectile in weapon.projectiles.iter() {
+                    win.sprite(
+                        projectile.p.x + dx,
+                        projectile.p.y + dy,
+                        sprite,
+                        projectile.dir,
+                    );
+                }
             }
         }
+
+        for shot in self.shots.iter() {
+            win.circle(shot.p.x + dx, shot.p.y + dy, SHOT_SIZE, [0xff, 0x44, 0xcc]);
+        }
+
+        for bolt in self.bolts.iter() {
+            win.line(
+                bolt.from.x + dx,
+                bolt.from.y + dy,
+                bolt.to.x + dx,
+                bolt.to.y + dy,
+                [0xcc, 0xee, 0xff],
+            );
+        }
     }
 }
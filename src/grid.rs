@@ -0,0 +1,76 @@
+use crate::game::Pos;
+
+use std::collections::HashMap;
+
+// Uniform spatial hash: objects are sorted into square cells so that lookups
+// only have to look at the cells around a position instead of every object.
+pub struct Grid<T> {
+    size: f32,
+    cells: HashMap<(i64, i64), Vec<(Pos, T)>>,
+}
+
+impl<T> Grid<T> {
+    pub fn new(size: f32) -> Self {
+        return Self {
+            size,
+            cells: HashMap::new(),
+        };
+    }
+
+    fn cell(&self, p: &Pos) -> (i64, i64) {
+        return (
+            (p.x / self.size).floor() as i64,
+            (p.y / self.size).floor() as i64,
+        );
+    }
+
+    // cells that overlap the square around p
+    fn cells_around(&self, p: &Pos, radius: f32) -> impl Iterator<Item = (i64, i64)> {
+        let (x0, y0) = self.cell(&Pos {
+            x: p.x - radius,
+            y: p.y - radius,
+        });
+        let (x1, y1) = self.cell(&Pos {
+            x: p.x + radius,
+            y: p.y + radius,
+        });
+        return (y0..=y1).flat_map(move |y| (x0..=x1).map(move |x| (x, y)));
+    }
+
+    // empties all cells but keeps their memory for the next step
+    pub fn clear(&mut self) {
+        self.cells.retain(|_, cell| {
+            let used = !cell.is_empty();
+            cell.clear();
+            return used;
+        });
+    }
+
+    pub fn insert(&mut self, p: Pos, value: T) {
+        let cell = self.cell(&p);
+        self.cells.entry(cell).or_default().push((p, value));
+    }
+
+    // all objects in the cells that overlap the circle, callers still need to
+    // check the exact distance
+    pub fn nearby(&self, p: &Pos, radius: f32) -> impl Iterator<Item = &(Pos, T)> {
+        return self
+            .cells_around(p, radius)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten();
+    }
+
+    // removes the objects near p for which f returns false, and the cells
+    // that end up empty so that the map does not grow without bounds
+    pub fn retain_nearby(&mut self, p: &Pos, radius: f32, mut f: impl FnMut(&Pos, &T) -> bool) {
+        let cells: Vec<(i64, i64)> = self.cells_around(p, radius).collect();
+        for key in cells {
+            if let Some(cell) = self.cells.get_mut(&key) {
+                cell.retain(|(p, value)| f(p, value));
+                if cell.is_empty() {
+                    self.cells.remove(&key);
+                }
+            }
+        }
+    }
+}
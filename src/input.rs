@@ -20,7 +20,7 @@ impl Input {
     }
 
     pub fn cbreak(&self) {
-        let mut t = self.termios.clone();
+        let mut t = self.termios;
         t.c_lflag &= !(libc::ICANON | libc::ECHO);
         t.c_cc[libc::VMIN] = 0;
         t.c_cc[libc::VTIME] = 0;
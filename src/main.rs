@@ -1,22 +1,36 @@
+#![allow(clippy::needless_return, clippy::identity_op)]
+
 extern crate libc;
 
+mod bench;
+mod defs;
 mod enemies;
 mod game;
+mod grid;
 mod input;
+mod perks;
 mod random;
 mod sprites;
 mod term;
 mod weapons;
 mod win;
+mod zones;
 
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::{thread, time};
 
 const TICK: time::Duration = time::Duration::from_millis(40);
+// share of TICK spent on a frame above which the enemy cap is lowered, and
+// below which it is raised
+const BUSY_HIGH: f32 = 0.8;
+const BUSY_LOW: f32 = 0.5;
 
 const BLACK: [u8; 3] = [0x00, 0x00, 0x00];
+const WHITE: [u8; 3] = [0xff, 0xff, 0xff];
+const GRAY: [u8; 3] = [0x22, 0x22, 0x33];
 const RED: [u8; 3] = [0xff, 0x00, 0x00];
 const BLUE: [u8; 3] = [0x00, 0x00, 0xff];
+const PURPLE: [u8; 3] = [0xaa, 0x00, 0xff];
 
 static NEED_QUIT: AtomicBool = AtomicBool::new(false);
 static NEED_RESIZE: AtomicBool = AtomicBool::new(false);
@@ -52,6 +66,111 @@ fn render_health_bar(player: &game::Player, screen: &mut term::Screen) {
     render_bar(screen, value, screen.height - 3, RED);
 }
 
+// shown above the health bar while a boss is alive, the row stays empty
+// otherwise
+fn render_boss_bar(game: &game::Game, screen: &mut term::Screen) {
+    let y0 = screen.height - 6;
+    match game.enemies.iter().find(|e| e.t.boss) {
+        Some(boss) => render_bar(screen, boss.health / boss.health_max, y0, PURPLE),
+        None => render_bar(screen, 1.0, y0, BLACK),
+    }
+}
+
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds as usize;
+    return format!("{:02}:{:02}", seconds / 60, seconds % 60);
+}
+
+fn render_timer(game: &game::Game, screen: &mut term::Screen) {
+    let text = format_time(game.time);
+    let x = (screen.width / 2).saturating_sub(text.len()) / 2;
+    screen.print(x, 1, &text, WHITE, BLACK);
+}
+
+// remaining rerolls, skips, and banishes in the top right corner
+fn render_tokens(player: &game::Player, screen: &mut term::Screen) {
+    let text = format!(
+        "reroll {}  skip {}  banish {}",
+        player.rerolls, player.skips, player.banishes
+    );
+    let x = (screen.width / 2).saturating_sub(text.len() + 1);
+    screen.print(x, 1, &text, WHITE, BLACK);
+}
+
+// x, y, and width are in terminal cells
+fn render_card(screen: &mut term::Screen, x0: usize, y0: usize, width: usize, text: &str) {
+    for y in (y0 * 3)..((y0 + 3) * 3).min(screen.height) {
+        for x in (x0 * 2)..((x0 + width) * 2).min(screen.width) {
+            screen.set(x, y, GRAY);
+        }
+    }
+    screen.print(x0 + 2, y0 + 1, text, WHITE, GRAY);
+}
+
+fn render_choice(game: &game::Game, screen: &mut term::Screen) {
+    if !game.paused() {
+        return;
+    }
+
+    let cols = screen.width / 2;
+    let rows = screen.height / 3;
+    let width = cols.min(44);
+    let x0 = (cols - width) / 2;
+    let y0 = rows.saturating_sub(game.choice.len() * 4 + 8) / 2;
+
+    let title = if game.banishing {
+        "Banish which perk?"
+    } else {
+        "Level up!"
+    };
+    render_card(screen, x0, y0, width, title);
+
+    for (i, choice) in game.choice.iter().enumerate() {
+        let text = format!("{}  {}", i + 1, perks::name(&game.player, *choice));
+        render_card(screen, x0, y0 + (i + 1) * 4, width, &text);
+    }
+
+    let player = &game.player;
+    let text = format!(
+        "r reroll ({})  x skip ({})  b banish ({})",
+        player.rerolls, player.skips, player.banishes
+    );
+    render_card(screen, x0, y0 + (game.choice.len() + 1) * 4, width, &text);
+}
+
+fn render_stats(player: &game::Player, screen: &mut term::Screen) {
+    let lines = [
+        format!("Health    {:.0} / {:.0}", player.health, player.health_max),
+        format!("Recovery  {:.1}/s", player.health_recover),
+        format!("Power     {:.1}", player.power),
+        format!("Speed     {:.0}", player.speed),
+        format!("Armor     {:.0}", player.armor),
+        format!("Evasion   {:.0}%", player.evasion * 100.0),
+        format!("Luck      {:.0}%", player.luck * 100.0),
+        format!("Cooldown  {:.0}%", player.cooldown_factor * 100.0),
+        format!("XP        {:.0}%", player.xp_factor * 100.0),
+        format!("Radius    {:.0}", player.damage_radius),
+        format!("Attract   {:.0}", player.diamond_radius),
+    ];
+
+    let cols = screen.width / 2;
+    let rows = screen.height / 3;
+    let width = cols.min(30);
+    let height = lines.len() + 4;
+    let x0 = (cols - width) / 2;
+    let y0 = rows.saturating_sub(height) / 2;
+
+    for y in (y0 * 3)..((y0 + height) * 3).min(screen.height) {
+        for x in (x0 * 2)..((x0 + width) * 2).min(screen.width) {
+            screen.set(x, y, GRAY);
+        }
+    }
+    screen.print(x0 + 2, y0 + 1, "Stats", WHITE, GRAY);
+    for (i, line) in lines.iter().enumerate() {
+        screen.print(x0 + 2, y0 + 3 + i, line, WHITE, GRAY);
+    }
+}
+
 fn signal(sig: libc::c_int, handler: libc::sighandler_t) {
     let mut action: libc::sigaction;
     unsafe {
@@ -64,15 +183,53 @@ fn signal(sig: libc::c_int, handler: libc::sighandler_t) {
     }
 }
 
-fn run_in_screen() -> Option<f32> {
+struct Score {
+    xp: f32,
+    time: f32,
+    won: bool,
+}
+
+// `busy` is the smoothed share of TICK spent on a frame
+fn adapt_enemy_cap(game: &mut game::Game, busy: f32) {
+    let step = (game.max_enemies / 100).max(1);
+    if busy > BUSY_HIGH {
+        game.max_enemies = game.max_enemies.saturating_sub(step).max(game::MIN_ENEMIES);
+    } else if busy < BUSY_LOW && game.enemies.len() >= game.max_enemies {
+        // only raise the cap if it is actually limiting the spawns
+        game.max_enemies = (game.max_enemies + step).min(game::MAX_ENEMIES);
+    }
+}
+
+fn run_in_screen(
+    weapons: Vec<weapons::Weapon>,
+    timeline: enemies::Timeline,
+    options: &Options,
+) -> Option<Score> {
     let pid = std::process::id();
     let mut input = input::Input::new();
     let mut screen = term::Screen::new();
-    let mut game = game::Game::new();
+    let mut game = game::Game::new(weapons, timeline);
+    if let Some(max_enemies) = options.max_enemies {
+        game.max_enemies = max_enemies;
+    }
+    game.difficulty = options.difficulty;
+    game.screen_shake = options.shake;
+    let mut busy = 0.0;
+    // the stats screen pauses the game
+    let mut stats = false;
 
-    signal(libc::SIGINT, handle_signal as libc::sighandler_t);
-    signal(libc::SIGWINCH, handle_signal as libc::sighandler_t);
-    signal(libc::SIGTSTP, handle_signal as libc::sighandler_t);
+    signal(
+        libc::SIGINT,
+        handle_signal as *const () as libc::sighandler_t,
+    );
+    signal(
+        libc::SIGWINCH,
+        handle_signal as *const () as libc::sighandler_t,
+    );
+    signal(
+        libc::SIGTSTP,
+        handle_signal as *const () as libc::sighandler_t,
+    );
 
     while !NEED_QUIT.load(Ordering::Relaxed) {
         if NEED_STOP.load(Ordering::Relaxed) {
@@ -108,6 +265,12 @@ fn run_in_screen() -> Option<f32> {
                     game.player.face = game::Dir::Right
                 }
                 b' ' => game.player.dir = None,
+                // the choice is hidden behind the stats screen
+                b'1'..=b'9' if !stats => game.choose((c - b'1') as usize),
+                b'r' if !stats => game.reroll(),
+                b'x' if !stats => game.skip(),
+                b'b' if !stats => game.toggle_banish(),
+                b'\t' => stats = !stats,
                 b'q' => NEED_QUIT.store(true, Ordering::Relaxed),
                 _ => {}
             }
@@ -115,37 +278,148 @@ fn run_in_screen() -> Option<f32> {
 
         let mut win = win::Window {
             width: screen.width,
-            height: screen.height - 6,
+            // the xp bar is above, the boss and health bars are below
+            height: screen.height - 9,
             dx: 0,
             dy: 3,
             screen: &mut screen,
         };
         let width = win::iconvert_x(win.width);
         let height = win::iconvert_y(win.height);
-        game.step(TICK.as_secs_f32(), width, height);
+        if !stats {
+            game.step(TICK.as_secs_f32(), width, height);
+        }
         game.render(&mut win);
 
         render_xp_bar(&game.player, &mut screen);
         render_health_bar(&game.player, &mut screen);
+        render_boss_bar(&game, &mut screen);
+        render_timer(&game, &mut screen);
+        render_tokens(&game.player, &mut screen);
+        render_choice(&game, &mut screen);
+        if stats {
+            render_stats(&game.player, &mut screen);
+        }
 
         screen.render();
 
-        if game.player.health < 0.0 {
-            return Some(game.player.xp);
+        if game.player.health <= 0.0 || game.won {
+            return Some(Score {
+                xp: game.player.xp,
+                time: game.time,
+                won: game.won,
+            });
         }
 
         let time2 = time::Instant::now();
+        // frames are cheap while paused and would raise the cap
+        if !stats && !game.paused() {
+            busy = busy * 0.9 + (time2 - time1).as_secs_f32() / TICK.as_secs_f32() * 0.1;
+            if options.max_enemies.is_none() {
+                adapt_enemy_cap(&mut game, busy);
+            }
+        }
         if TICK > time2 - time1 {
             thread::sleep(TICK - (time2 - time1));
         }
     }
 
-    return None
+    return None;
+}
+
+const USAGE: &str = "usage: survivor [--weapons FILE] [--enemies FILE] [--max-enemies N]
+                [--difficulty NAME] [--shake] [--bench]";
+
+struct Options {
+    weapons: Option<String>,
+    enemies: Option<String>,
+    max_enemies: Option<usize>,
+    difficulty: &'static enemies::Difficulty,
+    shake: bool,
+    bench: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        weapons: None,
+        enemies: None,
+        max_enemies: None,
+        difficulty: &enemies::DIFFICULTIES[1],
+        shake: false,
+        bench: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--weapons" => options.weapons = Some(args.next().ok_or("--weapons requires a file")?),
+            "--enemies" => options.enemies = Some(args.next().ok_or("--enemies requires a file")?),
+            "--max-enemies" => {
+                let n = args.next().ok_or("--max-enemies requires a number")?;
+                let n = n
+                    .parse()
+                    .map_err(|_| format!("--max-enemies requires a number, got `{}`", n))?;
+                options.max_enemies = Some(n);
+            }
+            "--difficulty" => {
+                let name = args.next().ok_or("--difficulty requires a name")?;
+                options.difficulty = enemies::Difficulty::find(&name).ok_or_else(|| {
+                    let names: Vec<&str> = enemies::DIFFICULTIES.iter().map(|d| d.name).collect();
+                    format!(
+                        "unknown difficulty `{}`, expected one of: {}",
+                        name,
+                        names.join(", ")
+                    )
+                })?;
+            }
+            "--shake" => options.shake = true,
+            "--bench" => options.bench = true,
+            _ => return Err(format!("unknown argument `{}`\n\n{}", arg, USAGE)),
+        }
+    }
+    return Ok(options);
+}
+
+// definitions are built into the binary but can be replaced at runtime
+fn load_definitions<T>(
+    path: &Option<String>,
+    default: &str,
+    load: fn(&str) -> Result<T, String>,
+) -> Result<T, String> {
+    return match path {
+        Some(path) => {
+            let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            load(&source).map_err(|e| format!("{}: {}", path, e))
+        }
+        None => load(default).map_err(|e| format!("built-in definitions: {}", e)),
+    };
 }
 
 fn main() {
-    let result = run_in_screen();
+    let definitions = parse_args().and_then(|options| {
+        let weapons = load_definitions(&options.weapons, weapons::DEFINITIONS, weapons::load)?;
+        let timeline = load_definitions(&options.enemies, enemies::DEFINITIONS, enemies::load)?;
+        return Ok((weapons, timeline, options));
+    });
+    let (weapons, timeline, options) = match definitions {
+        Ok(definitions) => definitions,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            std::process::exit(1);
+        }
+    };
+
+    if options.bench {
+        bench::run(weapons, timeline);
+        return;
+    }
+
+    let result = run_in_screen(weapons, timeline, &options);
     if let Some(score) = result {
-        println!("\nyou died (total XP: {:.0})", score);
+        let time = format_time(score.time);
+        if score.won {
+            println!("\nyou survived for {} (total XP: {:.0})", time, score.xp);
+        } else {
+            println!("\nyou died after {} (total XP: {:.0})", time, score.xp);
+        }
     }
 }
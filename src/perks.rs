@@ -0,0 +1,187 @@
+use crate::game::Player;
+
+const WEAPON_WEIGHT: f32 = 8.0;
+
+#[derive(Clone, Copy)]
+pub enum Choice {
+    Perk(usize),
+    Weapon(usize),
+    Evolution(usize),
+}
+
+pub struct Perk {
+    // used to refer to the perk in definition files
+    pub id: &'static str,
+    pub name: &'static str,
+    pub weight: f32,
+    pub max: Option<u8>,
+    pub eligible: fn(&Player) -> bool,
+    pub apply: fn(&mut Player),
+}
+
+fn always(_player: &Player) -> bool {
+    return true;
+}
+
+pub const PERKS: [Perk; 12] = [
+    Perk {
+        id: "power",
+        name: "Damage +10%",
+        weight: 10.0,
+        max: Some(10),
+        eligible: always,
+        apply: |player| player.power *= 1.1,
+    },
+    Perk {
+        id: "health",
+        name: "Max health +10%",
+        weight: 10.0,
+        max: Some(10),
+        eligible: always,
+        apply: |player| player.health_max *= 1.1,
+    },
+    Perk {
+        id: "speed",
+        name: "Movement speed +10%",
+        weight: 8.0,
+        max: Some(5),
+        eligible: always,
+        apply: |player| player.speed *= 1.1,
+    },
+    Perk {
+        id: "radius",
+        name: "Shield radius +10%",
+        weight: 8.0,
+        max: Some(8),
+        eligible: always,
+        apply: |player| player.damage_radius *= 1.1,
+    },
+    Perk {
+        id: "heal",
+        name: "Restore full health",
+        weight: 5.0,
+        max: None,
+        eligible: |player| player.health < player.health_max,
+        apply: |player| player.health = player.health_max,
+    },
+    Perk {
+        id: "recover",
+        name: "Recover +0.2HP per second",
+        weight: 6.0,
+        max: Some(10),
+        eligible: always,
+        apply: |player| player.health_recover += 0.2,
+    },
+    Perk {
+        id: "attract",
+        name: "Pickup radius +10%",
+        weight: 6.0,
+        max: Some(8),
+        eligible: always,
+        apply: |player| player.diamond_radius *= 1.1,
+    },
+    Perk {
+        id: "xp",
+        name: "Experience +10%",
+        weight: 6.0,
+        max: Some(8),
+        eligible: always,
+        apply: |player| player.xp_factor *= 1.1,
+    },
+    Perk {
+        id: "cooldown",
+        name: "Weapon cooldown -10%",
+        weight: 6.0,
+        max: Some(5),
+        eligible: always,
+        apply: |player| player.cooldown_factor *= 0.9,
+    },
+    Perk {
+        id: "armor",
+        name: "Armor +1",
+        weight: 5.0,
+        max: Some(5),
+        eligible: always,
+        apply: |player| player.armor += 1.0,
+    },
+    Perk {
+        id: "evasion",
+        name: "Evasion +5%",
+        weight: 4.0,
+        max: Some(6),
+        eligible: always,
+        apply: |player| player.evasion += 0.05,
+    },
+    Perk {
+        id: "luck",
+        name: "Luck +20%",
+        weight: 4.0,
+        max: Some(5),
+        eligible: always,
+        apply: |player| player.luck += 0.2,
+    },
+];
+
+pub fn available(player: &Player, perk: usize) -> bool {
+    let p = &PERKS[perk];
+    if player.banished[perk] || maxed(player, perk) {
+        return false;
+    }
+    return (p.eligible)(player);
+}
+
+pub fn find(id: &str) -> Option<usize> {
+    return PERKS.iter().position(|perk| perk.id == id);
+}
+
+pub fn maxed(player: &Player, perk: usize) -> bool {
+    return match PERKS[perk].max {
+        Some(max) => player.perks[perk] >= max,
+        None => false,
+    };
+}
+
+pub fn can_evolve(player: &Player, i: usize) -> bool {
+    let weapon = &player.weapons[i];
+    return match &weapon.evolution {
+        Some(evolution) => weapon.level >= weapon.max_level() && maxed(player, evolution.perk),
+        None => false,
+    };
+}
+
+pub fn can_upgrade(player: &Player, i: usize) -> bool {
+    let weapon = &player.weapons[i];
+    return !weapon.banished && weapon.level < weapon.max_level();
+}
+
+pub fn pool(player: &Player) -> Vec<(Choice, f32)> {
+    // luck evens out the weights, so rare choices come up more often
+    let weight = |w: f32| w.powf(1.0 / (1.0 + player.luck));
+
+    let mut pool = vec![];
+    for (perk, p) in PERKS.iter().enumerate() {
+        if available(player, perk) {
+            pool.push((Choice::Perk(perk), weight(p.weight)));
+        }
+    }
+    for i in 0..player.weapons.len() {
+        if can_upgrade(player, i) {
+            pool.push((Choice::Weapon(i), weight(WEAPON_WEIGHT)));
+        }
+    }
+    return pool;
+}
+
+pub fn name(player: &Player, choice: Choice) -> String {
+    return match choice {
+        Choice::Perk(perk) => PERKS[perk].name.to_string(),
+        Choice::Weapon(i) => player.weapons[i].describe_upgrade(),
+        Choice::Evolution(i) => match &player.weapons[i].evolution {
+            Some(evolution) => format!(
+                "Evolve {} into {}",
+                player.weapons[i].name, evolution.weapon.name
+            ),
+            None => unreachable!(),
+        },
+    };
+}
@@ -28,6 +28,18 @@ impl Rng {
         return low + self.generate() % (high - low);
     }
 
+    pub fn gen_weighted(&mut self, weights: &[f32]) -> usize {
+        let total: f32 = weights.iter().sum();
+        let mut x = self.gen_f32() * total;
+        for (i, weight) in weights.iter().enumerate() {
+            if x < *weight {
+                return i;
+            }
+            x -= weight;
+        }
+        return weights.len() - 1;
+    }
+
     pub fn gen_f32(&mut self) -> f32 {
         // https://en.wikipedia.org/wiki/Single-precision_floating-point_format
         let u = self.generate() as u32;
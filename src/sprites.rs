@@ -6,11 +6,19 @@ pub type Sprite = [[[u8; 3]; WIDTH]; HEIGHT];
 
 pub const PLAYER: Sprite = ppm::include_ppm!("player");
 pub const DIAMOND: Sprite = ppm::include_ppm!("diamond");
+pub const CHEST: Sprite = ppm::include_ppm!("chest");
 
 pub const AXE: Sprite = ppm::include_ppm!("axe");
 pub const KNIFE: Sprite = ppm::include_ppm!("knife");
 pub const STAR: Sprite = ppm::include_ppm!("star");
 pub const WIND: Sprite = ppm::include_ppm!("wind");
+pub const MISSILE: Sprite = ppm::include_ppm!("missile");
+pub const ORB: Sprite = ppm::include_ppm!("orb");
+pub const CROSS: Sprite = ppm::include_ppm!("cross");
+pub const SCYTHE: Sprite = ppm::include_ppm!("scythe");
+pub const THOUSAND: Sprite = ppm::include_ppm!("thousand");
+pub const ICE: Sprite = ppm::include_ppm!("ice");
+pub const FIRE: Sprite = ppm::include_ppm!("fire");
 
 pub const BAT: Sprite = ppm::include_ppm!("bat");
 pub const BAT2: Sprite = ppm::include_ppm!("bat2");
@@ -25,5 +33,43 @@ pub const RADDISH: Sprite = ppm::include_ppm!("raddish");
 pub const SHADOW: Sprite = ppm::include_ppm!("shadow");
 pub const SKELETON: Sprite = ppm::include_ppm!("skeleton");
 pub const SKELETON2: Sprite = ppm::include_ppm!("skeleton2");
+pub const REAPER: Sprite = ppm::include_ppm!("reaper");
 pub const SNAKE: Sprite = ppm::include_ppm!("snake");
 pub const ZOMBIE: Sprite = ppm::include_ppm!("zombie");
+
+// look up sprites from definition files by their file name
+pub fn by_name(name: &str) -> Option<&'static Sprite> {
+    return Some(match name {
+        "player" => &PLAYER,
+        "diamond" => &DIAMOND,
+        "chest" => &CHEST,
+        "axe" => &AXE,
+        "knife" => &KNIFE,
+        "star" => &STAR,
+        "wind" => &WIND,
+        "missile" => &MISSILE,
+        "orb" => &ORB,
+        "cross" => &CROSS,
+        "scythe" => &SCYTHE,
+        "thousand" => &THOUSAND,
+        "ice" => &ICE,
+        "fire" => &FIRE,
+        "bat" => &BAT,
+        "bat2" => &BAT2,
+        "crawl" => &CRAWL,
+        "eye" => &EYE,
+        "ghost" => &GHOST,
+        "hood" => &HOOD,
+        "hood2" => &HOOD2,
+        "mummy" => &MUMMY,
+        "plantguy" => &PLANTGUY,
+        "raddish" => &RADDISH,
+        "shadow" => &SHADOW,
+        "skeleton" => &SKELETON,
+        "skeleton2" => &SKELETON2,
+        "reaper" => &REAPER,
+        "snake" => &SNAKE,
+        "zombie" => &ZOMBIE,
+        _ => return None,
+    });
+}
@@ -54,7 +54,7 @@ fn sextant(block: u32) -> char {
     }
 }
 
-fn color_avg(colors: &Vec<[u8; 3]>) -> [u8; 3] {
+fn color_avg(colors: &[[u8; 3]]) -> [u8; 3] {
     let n = colors.len() as u16;
     if n == 0 {
         return [0, 0, 0];
@@ -89,10 +89,19 @@ fn get_block(colors: [[u8; 3]; 6]) -> (u32, [u8; 3], [u8; 3]) {
     return (block, color_avg(&darks), color_avg(&lights));
 }
 
+struct Label {
+    x: usize,
+    y: usize,
+    text: String,
+    fg: [u8; 3],
+    bg: [u8; 3],
+}
+
 pub struct Screen {
     pub width: usize,
     pub height: usize,
     pixels: Vec<Vec<[u8; 3]>>,
+    labels: Vec<Label>,
 }
 
 impl Screen {
@@ -101,6 +110,7 @@ impl Screen {
             width: 0,
             height: 0,
             pixels: vec![],
+            labels: vec![],
         };
         screen.init();
         return screen;
@@ -129,6 +139,21 @@ impl Screen {
         self.pixels[y][x] = color;
     }
 
+    // text is positioned in terminal cells, not pixels
+    pub fn print(&mut self, x: usize, y: usize, text: &str, fg: [u8; 3], bg: [u8; 3]) {
+        let cols = self.width / 2;
+        if y >= self.height / 3 || x >= cols {
+            return;
+        }
+        self.labels.push(Label {
+            x,
+            y,
+            text: text.chars().take(cols - x).collect(),
+            fg,
+            bg,
+        });
+    }
+
     pub fn render(&mut self) {
         let mut prev_bg = [0x00, 0x00, 0x00];
         let mut prev_fg = [0xff, 0xff, 0xff];
@@ -155,9 +180,20 @@ impl Screen {
                 print!("{}", sextant(block));
             }
             if y != self.height / 3 - 1 {
-                print!("\n");
+                println!();
             }
         }
+
+        for label in self.labels.drain(..) {
+            ti::cup(label.y, label.x);
+            ti::setab(label.bg);
+            ti::setaf(label.fg);
+            print!("{}", label.text);
+        }
+
+        // the next frame assumes these colors
+        ti::setab([0x00, 0x00, 0x00]);
+        ti::setaf([0xff, 0xff, 0xff]);
     }
 }
 
@@ -1,26 +1,188 @@
+use crate::defs;
+use crate::enemies;
 use crate::game::{Dir, Pos};
+use crate::perks;
 use crate::sprites;
 
 pub const SPAWN_RADIUS: f32 = 16.0;
+const TURN_RATE: f32 = 4.0;
+const ORBIT_RADIUS: f32 = 40.0;
+const BOOMERANG_TURN: f32 = 0.8;
+const CHAIN_RANGE: f32 = 100.0;
+pub const BOLT_DURATION: f32 = 0.2;
 
+#[derive(Clone)]
 pub struct Projectile {
     pub p: Pos,
     pub dir: Dir,
+    pub angle: f32,
+    pub age: f32,
+    pub pierce: Option<u8>,
+    // enemy ids and the age at which they were hit
+    pub hits: Vec<(usize, f32)>,
 }
 
+impl Projectile {
+    pub fn spent(&self) -> bool {
+        return self.pierce == Some(0);
+    }
+
+    pub fn can_hit(&self, id: usize, cooldown: f32) -> bool {
+        return !self.spent()
+            && self
+                .hits
+                .iter()
+                .all(|(i, age)| *i != id || self.age - age >= cooldown);
+    }
+
+    pub fn hit(&mut self, id: usize, cooldown: f32) {
+        let age = self.age;
+        self.hits.retain(|(i, t)| *i != id && age - t < cooldown);
+        self.hits.push((id, age));
+        if let Some(pierce) = &mut self.pierce {
+            *pierce -= 1;
+        }
+    }
+}
+
+pub struct Context<'a> {
+    pub center: &'a Pos,
+    pub enemies: &'a [enemies::Enemy],
+}
+
+#[derive(Clone, Copy)]
+pub enum Upgrade {
+    Amount(u8),
+    Damage(f32),
+    Speed(f32),
+    Size(f32),
+    Cooldown(f32),
+    Duration(f32),
+    Pierce(u8),
+    Jumps(u8),
+}
+
+impl Upgrade {
+    pub fn describe(&self) -> String {
+        return match self {
+            Self::Amount(n) => format!("+{} projectile", n),
+            Self::Damage(f) => format!("+{:.0}% damage", f * 100.0),
+            Self::Speed(f) => format!("+{:.0}% speed", f * 100.0),
+            Self::Size(f) => format!("+{:.0}% size", f * 100.0),
+            Self::Cooldown(f) => format!("-{:.0}% cooldown", f * 100.0),
+            Self::Duration(f) => format!("+{:.0}% duration", f * 100.0),
+            Self::Pierce(n) => format!("pierces {} more enemy", n),
+            Self::Jumps(n) => format!("jumps to {} more enemy", n),
+        };
+    }
+
+    fn apply(&self, weapon: &mut Weapon) {
+        match self {
+            Self::Amount(n) => weapon.amount += n,
+            Self::Damage(f) => weapon.damage *= 1.0 + f,
+            Self::Speed(f) => weapon.speed *= 1.0 + f,
+            Self::Size(f) => weapon.size *= 1.0 + f,
+            Self::Cooldown(f) => weapon.cooldown *= 1.0 - f,
+            Self::Duration(f) => weapon.duration *= 1.0 + f,
+            Self::Pierce(n) => {
+                if let Some(pierce) = &mut weapon.pierce {
+                    *pierce += n;
+                }
+            }
+            Self::Jumps(n) => {
+                if let Kind::Chain { jumps, .. } = &mut weapon.kind {
+                    *jumps += n;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum Kind {
+    Projectile(fn(&mut Projectile, &Context, speed: f32, dt: f32) -> ()),
+    // circles around the player, the projectiles are spread evenly
+    Orbit,
+    // instantly strikes the nearest enemy and jumps to others nearby
+    Chain { jumps: u8, radius: f32 },
+    // leaves a damaging zone with the weapon's size as radius
+    Zone,
+}
+
+// short-lived line that is drawn for chain strikes
+pub struct Bolt {
+    pub from: Pos,
+    pub to: Pos,
+    pub age: f32,
+}
+
+#[derive(Clone)]
+pub struct Evolution {
+    // the perk that needs to be maxed out
+    pub perk: usize,
+    pub weapon: Box<Weapon>,
+}
+
+#[derive(Clone)]
 pub struct Weapon {
-    pub sprite: &'static sprites::Sprite,
-    pub _move: fn(&mut Projectile, &Pos, speed: f32, dt: f32) -> (),
+    pub name: String,
+    // drawn for every projectile, chains and zones have none
+    pub sprite: Option<&'static sprites::Sprite>,
+    pub kind: Kind,
     pub speed: f32,
     pub damage: f32,
     pub cooldown: f32,
     pub size: f32,
+    // projectiles are removed after this many seconds
+    pub duration: f32,
+    // number of enemies a projectile can hit before it is removed
+    pub pierce: Option<u8>,
+    // seconds before a projectile can hit the same enemy again
+    pub hit_cooldown: f32,
     pub amount: u8,
+    pub level: u8,
+    // upgrades for levels 2 and above, level 1 adds the first projectile
+    pub levels: Vec<Upgrade>,
+    pub banished: bool,
     pub last: f32,
     pub projectiles: Vec<Projectile>,
+    pub evolution: Option<Evolution>,
+    // status effect and its duration in seconds
+    pub effect: Option<(enemies::Effect, f32)>,
+    // distance enemies are pushed back on hit
+    pub knockback: f32,
 }
 
-pub fn move_straight(projectile: &mut Projectile, _center: &Pos, speed: f32, dt: f32) {
+impl Weapon {
+    pub fn max_level(&self) -> u8 {
+        return self.levels.len() as u8 + 1;
+    }
+
+    pub fn describe_upgrade(&self) -> String {
+        if self.level == 0 {
+            return format!("New weapon: {}", self.name);
+        }
+        let upgrade = self.levels[self.level as usize - 1];
+        return format!(
+            "{} level {}: {}",
+            self.name,
+            self.level + 1,
+            upgrade.describe()
+        );
+    }
+
+    pub fn upgrade(&mut self) {
+        if self.level == 0 {
+            self.amount = 1;
+        } else {
+            let upgrade = self.levels[self.level as usize - 1];
+            upgrade.apply(self);
+        }
+        self.level += 1;
+    }
+}
+
+pub fn move_straight(projectile: &mut Projectile, _ctx: &Context, speed: f32, dt: f32) {
     match projectile.dir {
         Dir::Up => projectile.p.y -= speed * dt,
         Dir::Right => projectile.p.x += speed * dt,
@@ -29,7 +191,21 @@ pub fn move_straight(projectile: &mut Projectile, _center: &Pos, speed: f32, dt:
     }
 }
 
-pub fn move_diagonal(projectile: &mut Projectile, center: &Pos, speed: f32, dt: f32) {
+pub fn move_spread(projectile: &mut Projectile, ctx: &Context, speed: f32, dt: f32) {
+    let center = ctx.center;
+    let dx = projectile.p.x - center.x;
+    let dy = projectile.p.y - center.y;
+
+    // drift sideways so that the projectiles fan out
+    match projectile.dir {
+        Dir::Up | Dir::Down => projectile.p.x += speed * dx / (dy.abs() + SPAWN_RADIUS) * dt,
+        Dir::Right | Dir::Left => projectile.p.y += speed * dy / (dx.abs() + SPAWN_RADIUS) * dt,
+    }
+    move_straight(projectile, ctx, speed, dt);
+}
+
+pub fn move_diagonal(projectile: &mut Projectile, ctx: &Context, speed: f32, dt: f32) {
+    let center = ctx.center;
     let mut dx = projectile.p.x - center.x;
     let dy = projectile.p.y - center.y;
     if dx == 0.0 && dy == 0.0 {
@@ -43,7 +219,8 @@ pub fn move_diagonal(projectile: &mut Projectile, center: &Pos, speed: f32, dt:
     projectile.dir = if dx < 0.0 { Dir::Left } else { Dir::Right };
 }
 
-pub fn move_parabola(projectile: &mut Projectile, center: &Pos, speed: f32, dt: f32) {
+pub fn move_parabola(projectile: &mut Projectile, ctx: &Context, speed: f32, dt: f32) {
+    let center = ctx.center;
     let dx = (projectile.p.x - center.x).abs();
     let mut t = (dx / speed).max(0.1);
     if dx < SPAWN_RADIUS && projectile.p.y > center.y {
@@ -64,7 +241,8 @@ pub fn move_parabola(projectile: &mut Projectile, center: &Pos, speed: f32, dt:
     }
 }
 
-pub fn move_spiral(projectile: &mut Projectile, center: &Pos, speed: f32, dt: f32) {
+pub fn move_spiral(projectile: &mut Projectile, ctx: &Context, speed: f32, dt: f32) {
+    let center = ctx.center;
     let dx = projectile.p.x - center.x;
     let dy = projectile.p.y - center.y;
     let r = f32::sqrt(dx * dx + dy * dy);
@@ -85,51 +263,306 @@ pub fn move_spiral(projectile: &mut Projectile, center: &Pos, speed: f32, dt: f3
     };
 }
 
-pub fn create_weapons() -> Vec<Weapon> {
-    return vec![
-        Weapon {
-            sprite: &sprites::AXE,
-            _move: move_parabola,
-            speed: 150.0,
-            damage: 50.0,
-            cooldown: 10.0,
-            size: 7.0,
-            last: 0.0,
-            amount: 0,
-            projectiles: vec![],
-        },
-        Weapon {
-            sprite: &sprites::KNIFE,
-            _move: move_straight,
-            speed: 200.0,
-            damage: 30.0,
-            cooldown: 4.0,
-            size: 6.0,
-            last: 0.0,
-            amount: 0,
-            projectiles: vec![],
-        },
-        Weapon {
-            sprite: &sprites::STAR,
-            _move: move_diagonal,
-            speed: 250.0,
-            damage: 20.0,
-            cooldown: 3.0,
-            size: 6.0,
-            last: 0.0,
-            amount: 0,
-            projectiles: vec![],
+pub fn move_homing(projectile: &mut Projectile, ctx: &Context, speed: f32, dt: f32) {
+    let nearest = ctx.enemies.iter().min_by_key(|enemy| {
+        let dx = enemy.p.x - projectile.p.x;
+        let dy = enemy.p.y - projectile.p.y;
+        return (dx * dx + dy * dy) as i64;
+    });
+
+    if let Some(enemy) = nearest {
+        let target = (enemy.p.y - projectile.p.y).atan2(enemy.p.x - projectile.p.x);
+        let mut delta = target - projectile.angle;
+        if delta > std::f32::consts::PI {
+            delta -= std::f32::consts::TAU;
+        } else if delta < -std::f32::consts::PI {
+            delta += std::f32::consts::TAU;
+        }
+        let max = TURN_RATE * dt;
+        projectile.angle += delta.max(-max).min(max);
+    }
+
+    let (sin, cos) = projectile.angle.sin_cos();
+    projectile.p.x += cos * speed * dt;
+    projectile.p.y += sin * speed * dt;
+    projectile.dir = Dir::from_angle(projectile.angle);
+}
+
+pub fn move_orbit(projectile: &mut Projectile, ctx: &Context, speed: f32, dt: f32) {
+    projectile.angle += speed / ORBIT_RADIUS * dt;
+
+    let (sin, cos) = projectile.angle.sin_cos();
+    projectile.p.x = ctx.center.x + cos * ORBIT_RADIUS;
+    projectile.p.y = ctx.center.y + sin * ORBIT_RADIUS;
+}
+
+pub fn move_boomerang(projectile: &mut Projectile, ctx: &Context, speed: f32, dt: f32) {
+    let v = speed * (1.0 - projectile.age / BOOMERANG_TURN);
+    if v > 0.0 {
+        move_straight(projectile, ctx, v, dt);
+        return;
+    }
+
+    // fly back through the player's current position
+    let dx = ctx.center.x - projectile.p.x;
+    let dy = ctx.center.y - projectile.p.y;
+    if dx * dx + dy * dy > SPAWN_RADIUS * SPAWN_RADIUS {
+        projectile.angle = dy.atan2(dx);
+    }
+
+    let (sin, cos) = projectile.angle.sin_cos();
+    let v = (-v).min(speed * 1.5);
+    projectile.p.x += cos * v * dt;
+    projectile.p.y += sin * v * dt;
+}
+
+fn nearest<'a>(
+    enemies: &'a mut [enemies::Enemy],
+    p: &Pos,
+    radius: f32,
+    struck: &[usize],
+) -> Option<&'a mut enemies::Enemy> {
+    return enemies
+        .iter_mut()
+        .filter(|enemy| enemy.p.in_radius(p, radius) && !struck.contains(&enemy.id))
+        .min_by_key(|enemy| {
+            let dx = enemy.p.x - p.x;
+            let dy = enemy.p.y - p.y;
+            return (dx * dx + dy * dy) as i64;
+        });
+}
+
+pub fn strike_chain(
+    enemies: &mut [enemies::Enemy],
+    center: &Pos,
+    jumps: u8,
+    radius: f32,
+    damage: f32,
+    struck: &mut Vec<usize>,
+    bolts: &mut Vec<Bolt>,
+) {
+    let mut from = *center;
+    let mut range = CHAIN_RANGE;
+    for _ in 0..=jumps {
+        match nearest(enemies, &from, range, struck) {
+            Some(enemy) => {
+                enemy.health -= damage;
+                struck.push(enemy.id);
+                bolts.push(Bolt {
+                    from,
+                    to: enemy.p,
+                    age: 0.0,
+                });
+                from = enemy.p;
+                range = radius;
+            }
+            None => break,
+        }
+    }
+}
+
+pub const DEFINITIONS: &str = include_str!("../data/weapons.txt");
+
+const KEYS: [&str; 16] = [
+    "sprite",
+    "move",
+    "speed",
+    "damage",
+    "cooldown",
+    "size",
+    "duration",
+    "pierce",
+    "hit_cooldown",
+    "jumps",
+    "radius",
+    "levels",
+    "evolution",
+    "evolution_perk",
+    "effect",
+    "knockback",
+];
+
+fn parse_kind(section: &defs::Section) -> Result<Kind, String> {
+    let field = section.require("move")?;
+    return Ok(match field.value.as_str() {
+        "straight" => Kind::Projectile(move_straight),
+        "spread" => Kind::Projectile(move_spread),
+        "diagonal" => Kind::Projectile(move_diagonal),
+        "parabola" => Kind::Projectile(move_parabola),
+        "spiral" => Kind::Projectile(move_spiral),
+        "homing" => Kind::Projectile(move_homing),
+        "orbit" => Kind::Orbit,
+        "boomerang" => Kind::Projectile(move_boomerang),
+        "chain" => Kind::Chain {
+            jumps: section.require("jumps")?.u8()?,
+            radius: section.require("radius")?.f32()?,
         },
-        Weapon {
-            sprite: &sprites::WIND,
-            _move: move_spiral,
-            speed: 100.0,
-            damage: 40.0,
-            cooldown: 9.0,
-            size: 8.0,
-            last: 0.0,
-            amount: 0,
-            projectiles: vec![],
+        "zone" => Kind::Zone,
+        other => return Err(field.error(&format!("unknown move `{}`", other))),
+    });
+}
+
+fn parse_upgrade(field: &defs::Field, text: &str) -> Result<Upgrade, String> {
+    let error = |msg: &str| field.error(&format!("invalid upgrade `{}`: {}", text, msg));
+
+    let (kind, value) = text
+        .split_once(' ')
+        .ok_or_else(|| error("expected a kind and a value"))?;
+    let value = value.trim();
+
+    let count = || {
+        value
+            .parse::<u8>()
+            .map_err(|_| error("expected a whole number"))
+    };
+    let percent = || {
+        return value
+            .strip_suffix('%')
+            .and_then(|v| v.parse::<f32>().ok())
+            .map(|v| v / 100.0)
+            .ok_or_else(|| error("expected a percentage like `20%`"));
+    };
+
+    return Ok(match kind {
+        "amount" => Upgrade::Amount(count()?),
+        "pierce" => Upgrade::Pierce(count()?),
+        "jumps" => Upgrade::Jumps(count()?),
+        "damage" => Upgrade::Damage(percent()?),
+        "speed" => Upgrade::Speed(percent()?),
+        "size" => Upgrade::Size(percent()?),
+        "cooldown" => Upgrade::Cooldown(percent()?),
+        "duration" => Upgrade::Duration(percent()?),
+        _ => return Err(error("unknown kind")),
+    });
+}
+
+fn parse_effect(field: &defs::Field) -> Result<(enemies::Effect, f32), String> {
+    let error = || {
+        field.error(&format!(
+            "expected `slow`, `freeze`, `burn`, or `poison` followed by seconds, got `{}`",
+            field.value
+        ))
+    };
+
+    let (name, duration) = field.value.split_once(' ').ok_or_else(error)?;
+    let effect = match name {
+        "slow" => enemies::Effect::Slow,
+        "freeze" => enemies::Effect::Freeze,
+        "burn" => enemies::Effect::Burn,
+        "poison" => enemies::Effect::Poison,
+        _ => return Err(error()),
+    };
+    return Ok((effect, duration.trim().parse().map_err(|_| error())?));
+}
+
+fn parse_weapon(section: &defs::Section) -> Result<Weapon, String> {
+    section.check_keys(&KEYS)?;
+
+    let kind = parse_kind(section)?;
+    let sprite = match kind {
+        Kind::Chain { .. } | Kind::Zone => {
+            if let Some(field) = section.get("sprite") {
+                return Err(field.error("chain and zone weapons are not drawn with a sprite"));
+            }
+            None
+        }
+        _ => {
+            let field = section.require("sprite")?;
+            let sprite = sprites::by_name(&field.value)
+                .ok_or_else(|| field.error(&format!("unknown sprite `{}`", field.value)))?;
+            Some(sprite)
+        }
+    };
+    let levels = match section.get("levels") {
+        Some(field) => field
+            .list()
+            .iter()
+            .map(|text| parse_upgrade(field, text))
+            .collect::<Result<Vec<Upgrade>, String>>()?,
+        None => vec![],
+    };
+    let pierce = match section.get("pierce") {
+        Some(field) => Some(field.u8()?),
+        None => None,
+    };
+
+    // upgrades that would have no effect on this weapon
+    if let Some(field) = section.get("levels") {
+        for upgrade in levels.iter() {
+            match upgrade {
+                Upgrade::Pierce(_) if pierce.is_none() => {
+                    return Err(field.error("`pierce` upgrades require a `pierce` value"));
+                }
+                Upgrade::Jumps(_) if !matches!(kind, Kind::Chain { .. }) => {
+                    return Err(field.error("`jumps` upgrades only work for chain weapons"));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    return Ok(Weapon {
+        name: section.name.clone(),
+        sprite,
+        kind,
+        speed: section.f32_or("speed", 0.0)?,
+        damage: section.require("damage")?.f32()?,
+        cooldown: section.require("cooldown")?.f32()?,
+        size: section.f32_or("size", 0.0)?,
+        duration: section.f32_or("duration", f32::INFINITY)?,
+        pierce,
+        hit_cooldown: section.f32_or("hit_cooldown", 0.5)?,
+        amount: 0,
+        level: 0,
+        levels,
+        banished: false,
+        last: 0.0,
+        projectiles: vec![],
+        evolution: None,
+        effect: match section.get("effect") {
+            Some(field) => Some(parse_effect(field)?),
+            None => None,
         },
-    ];
+        knockback: section.f32_or("knockback", 10.0)?,
+    });
+}
+
+pub fn load(source: &str) -> Result<Vec<Weapon>, String> {
+    let sections = defs::parse(source)?;
+    let mut weapons = sections
+        .iter()
+        .map(parse_weapon)
+        .collect::<Result<Vec<Weapon>, String>>()?;
+
+    let mut evolved = vec![];
+    for (i, section) in sections.iter().enumerate() {
+        if let Some(field) = section.get("evolution") {
+            let target = sections
+                .iter()
+                .position(|s| s.name == field.value)
+                .ok_or_else(|| field.error(&format!("unknown weapon `{}`", field.value)))?;
+            if sections[target].get("evolution").is_some() {
+                return Err(field.error(&format!("`{}` cannot evolve any further", field.value)));
+            }
+
+            let perk_field = section.require("evolution_perk")?;
+            let perk = perks::find(&perk_field.value)
+                .ok_or_else(|| perk_field.error(&format!("unknown perk `{}`", perk_field.value)))?;
+
+            weapons[i].evolution = Some(Evolution {
+                perk,
+                weapon: Box::new(weapons[target].clone()),
+            });
+            evolved.push(target);
+        } else if let Some(field) = section.get("evolution_perk") {
+            return Err(field.error("`evolution_perk` requires `evolution`"));
+        }
+    }
+
+    return Ok(weapons
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !evolved.contains(i))
+        .map(|(_, weapon)| weapon)
+        .collect());
 }
@@ -20,6 +20,18 @@ pub fn iconvert_y(y: usize) -> f32 {
     return y as f32 * ASPECT_RATIO;
 }
 
+// mixes the color half and half with the tint
+fn apply_tint(c: [u8; 3], tint: Option<[u8; 3]>) -> [u8; 3] {
+    return match tint {
+        Some(t) => [
+            ((c[0] as u16 + t[0] as u16) / 2) as u8,
+            ((c[1] as u16 + t[1] as u16) / 2) as u8,
+            ((c[2] as u16 + t[2] as u16) / 2) as u8,
+        ],
+        None => c,
+    };
+}
+
 pub struct Window<'a> {
     pub height: usize,
     pub width: usize,
@@ -41,11 +53,19 @@ impl Window<'_> {
         }
     }
 
-    pub fn _sprite(&mut self, cx: f32, cy: f32, sprite: &sprites::Sprite, invert: bool) {
-        let x0 = convert_x(cx) - sprites::WIDTH as i64 / 2;
-        let y0 = convert_y(cy) - (sprites::HEIGHT as i64 - sprites::WIDTH as i64 / 2);
-
-        for dy in 0..sprites::HEIGHT {
+    pub fn _sprite(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        sprite: &sprites::Sprite,
+        invert: bool,
+        scale: usize,
+        tint: Option<[u8; 3]>,
+    ) {
+        let x0 = convert_x(cx) - (sprites::WIDTH * scale) as i64 / 2;
+        let y0 = convert_y(cy) - ((sprites::HEIGHT - sprites::WIDTH / 2) * scale) as i64;
+
+        for dy in 0..sprites::HEIGHT * scale {
             let y = y0 + dy as i64;
             if y < 0 {
                 continue;
@@ -53,7 +73,7 @@ impl Window<'_> {
             if y >= self.height as i64 {
                 break;
             }
-            for dx in 0..sprites::WIDTH {
+            for dx in 0..sprites::WIDTH * scale {
                 let x = x0 + dx as i64;
                 if x < 0 {
                     continue;
@@ -61,20 +81,29 @@ impl Window<'_> {
                 if x >= self.width as i64 {
                     break;
                 }
-                let cx = if invert { sprites::WIDTH - dx - 1 } else { dx };
-                let c = sprite[dy][cx];
+                let cx = dx / scale;
+                let cx = if invert { sprites::WIDTH - cx - 1 } else { cx };
+                let c = sprite[dy / scale][cx];
                 if c != sprite[0][0] {
-                    self.set(x as usize, y as usize, c);
+                    self.set(x as usize, y as usize, apply_tint(c, tint));
                 }
             }
         }
     }
 
-    pub fn _sprite_tilted(&mut self, cx: f32, cy: f32, sprite: &sprites::Sprite, invert: bool) {
-        let x0 = convert_x(cx) - sprites::WIDTH as i64 / 2;
-        let y0 = convert_y(cy) - sprites::WIDTH as i64 / 2;
-
-        for dy in 0..sprites::WIDTH {
+    pub fn _sprite_tilted(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        sprite: &sprites::Sprite,
+        invert: bool,
+        scale: usize,
+        tint: Option<[u8; 3]>,
+    ) {
+        let x0 = convert_x(cx) - (sprites::WIDTH * scale) as i64 / 2;
+        let y0 = convert_y(cy) - (sprites::WIDTH * scale) as i64 / 2;
+
+        for dy in 0..sprites::WIDTH * scale {
             let y = y0 + dy as i64;
             if y < 0 {
                 continue;
@@ -82,7 +111,7 @@ impl Window<'_> {
             if y >= self.height as i64 {
                 break;
             }
-            for dx in 0..sprites::HEIGHT {
+            for dx in 0..sprites::HEIGHT * scale {
                 let x = x0 + dx as i64;
                 if x < 0 {
                     continue;
@@ -90,21 +119,36 @@ impl Window<'_> {
                 if x >= self.width as i64 {
                     break;
                 }
-                let cy = if invert { sprites::WIDTH - dy - 1 } else { dy };
-                let c = sprite[dx][cy];
+                let cy = dy / scale;
+                let cy = if invert { sprites::WIDTH - cy - 1 } else { cy };
+                let c = sprite[dx / scale][cy];
                 if c != sprite[0][0] {
-                    self.set(x as usize, y as usize, c);
+                    self.set(x as usize, y as usize, apply_tint(c, tint));
                 }
             }
         }
     }
 
     pub fn sprite(&mut self, cx: f32, cy: f32, sprite: &sprites::Sprite, face: Dir) {
+        self.scaled_sprite(cx, cy, sprite, face, 1, None);
+    }
+
+    // every pixel of the sprite becomes a scale x scale square, colors are
+    // optionally mixed with a tint
+    pub fn scaled_sprite(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        sprite: &sprites::Sprite,
+        face: Dir,
+        scale: usize,
+        tint: Option<[u8; 3]>,
+    ) {
         match face {
-            Dir::Up => self._sprite_tilted(cx, cy, sprite, true),
-            Dir::Right => self._sprite(cx, cy, sprite, false),
-            Dir::Down => self._sprite_tilted(cx, cy, sprite, false),
-            Dir::Left => self._sprite(cx, cy, sprite, true),
+            Dir::Up => self._sprite_tilted(cx, cy, sprite, true, scale, tint),
+            Dir::Right => self._sprite(cx, cy, sprite, false, scale, tint),
+            Dir::Down => self._sprite_tilted(cx, cy, sprite, false, scale, tint),
+            Dir::Left => self._sprite(cx, cy, sprite, true, scale, tint),
         }
     }
 
@@ -128,4 +172,33 @@ impl Window<'_> {
             }
         }
     }
+
+    pub fn line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, color: [u8; 3]) {
+        // https://en.wikipedia.org/wiki/Bresenham%27s_line_algorithm
+        let (mut x, mut y) = (convert_x(x0), convert_y(y0));
+        let (x1, y1) = (convert_x(x1), convert_y(y1));
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let sx = if x < x1 { 1 } else { -1 };
+        let sy = if y < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        loop {
+            if x >= 0 && y >= 0 && x < self.width as i64 && y < self.height as i64 {
+                self.set(x as usize, y as usize, color);
+            }
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
 }
@@ -0,0 +1,29 @@
+use crate::enemies;
+use crate::game::Pos;
+
+pub struct Zone {
+    pub p: Pos,
+    pub radius: f32,
+    // damage per second
+    pub damage: f32,
+    pub duration: f32,
+    pub age: f32,
+    pub color: [u8; 3],
+    // status effect and its duration in seconds after leaving the zone
+    pub effect: Option<(enemies::Effect, f32)>,
+}
+
+impl Zone {
+    pub fn apply(&self, enemy: &mut enemies::Enemy, dt: f32) {
+        if enemy.p.in_radius(&self.p, self.radius) {
+            enemy.health -= self.damage * dt;
+            if let Some((effect, duration)) = self.effect {
+                enemy.afflict(effect, duration, self.damage);
+            }
+        }
+    }
+
+    pub fn expired(&self) -> bool {
+        return self.age >= self.duration;
+    }
+}